@@ -18,6 +18,38 @@ pub extern "C" fn gxt_make_key() -> *mut c_char {
     cstr.into_raw()
 }
 
+/// Exports a key as a recovery phrase of 24 words.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_key_to_recovery_phrase(key: *const c_char) -> *mut c_char {
+    let key = unsafe { CStr::from_ptr(key) };
+    let phrase = gxt::key_to_recovery_phrase(key.to_str().expect(E_C_TO_RUST_STRING))
+        .expect("Failed to export key");
+    let cstr = CString::new(phrase).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
+/// Restores a key from a recovery phrase and returns it as gxt message.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_key_from_recovery_phrase(phrase: *const c_char) -> *mut c_char {
+    let phrase = unsafe { CStr::from_ptr(phrase) };
+    let key = gxt::key_from_recovery_phrase(phrase.to_str().expect(E_C_TO_RUST_STRING))
+        .expect("Failed to restore key");
+    let cstr = CString::new(key).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
 /// Creates a new id card from a key and returns it as gxt message.
 ///
 /// # Safety
@@ -56,6 +88,36 @@ pub unsafe extern "C" fn gxt_verify_message(msg: *const c_char) -> *mut c_char {
     cstr.into_raw()
 }
 
+/// Verifies many tokens at once, one token per line, and returns a JSON array with one entry per token,
+/// which contains either the `envelope` or the `error`.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_verify_many(tokens: *const c_char) -> *mut c_char {
+    let tokens = unsafe { CStr::from_ptr(tokens) };
+    let tokens = tokens
+        .to_str()
+        .expect(E_C_TO_RUST_STRING)
+        .lines()
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .collect::<Vec<_>>();
+    let results = gxt::verify_many::<serde_json::Value, _>(&tokens)
+        .into_iter()
+        .map(|result| match result {
+            Ok(envelope) => serde_json::json!({ "envelope": envelope, "error": null }),
+            Err(e) => serde_json::json!({ "envelope": null, "error": e.to_string() }),
+        })
+        .collect::<Vec<_>>();
+    let cstr = CString::new(serde_json::to_string(&results).expect(E_JSON_PARSE))
+        .expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
 /// Encrypts the payload and returns the gxt message containing the encrypted data.
 ///
 /// # Safety
@@ -140,6 +202,114 @@ pub unsafe extern "C" fn gxt_decrypt_message(
     cstr.into_raw()
 }
 
+/// Re-encodes a gxt message with the given encoding (`base58`, `qr`, `base64url` or `bech32`)
+/// and returns it as string.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_convert_token(
+    token: *const c_char,
+    encoding: *const c_char,
+) -> *mut c_char {
+    let token = unsafe { CStr::from_ptr(token) };
+    let encoding = unsafe { CStr::from_ptr(encoding) };
+    let encoding = encoding
+        .to_str()
+        .expect(E_C_TO_RUST_STRING)
+        .parse()
+        .expect("Unknown encoding");
+    let token = gxt::convert_token(token.to_str().expect(E_C_TO_RUST_STRING), encoding)
+        .expect("Failed to convert token");
+    let cstr = CString::new(token).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
+/// Splits a gxt message into parts of at most `max_part_len` characters
+/// and returns them as string, one part per line.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_split_into_parts(
+    token: *const c_char,
+    max_part_len: usize,
+) -> *mut c_char {
+    let token = unsafe { CStr::from_ptr(token) };
+    let parts = gxt::split_into_parts(token.to_str().expect(E_C_TO_RUST_STRING), max_part_len)
+        .expect("Failed to split token");
+    let cstr = CString::new(parts.join("\n")).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
+/// Joins parts, one per line in any order, and returns the gxt message as string.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_join_parts(parts: *const c_char) -> *mut c_char {
+    let parts = unsafe { CStr::from_ptr(parts) };
+    let parts = parts
+        .to_str()
+        .expect(E_C_TO_RUST_STRING)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    let token = gxt::join_parts(&parts).expect("Failed to join parts");
+    let cstr = CString::new(token).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
+/// Finds all tokens in arbitrary text, like chat logs, and returns them as string, one token per line.
+///
+/// # Safety
+/// - Returned string must be freed with [`gxt_free_string`] after use.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_extract_tokens(text: *const c_char) -> *mut c_char {
+    let text = unsafe { CStr::from_ptr(text) };
+    let tokens = gxt::extract_tokens(text.to_str().expect(E_C_TO_RUST_STRING))
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let cstr = CString::new(tokens.join("\n")).expect(E_RUST_TO_C_STRING);
+    cstr.into_raw()
+}
+
+/// Registers a custom zstd dictionary, so tokens that were compressed with it can be decoded.
+/// If `use_for_new_tokens` is set, new tokens are compressed with it as well.
+///
+/// # Safety
+/// - `dictionary` must point to `len` readable bytes.
+///
+/// # Panics
+/// - Currently panics on error.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gxt_register_dictionary(
+    dictionary: *const u8,
+    len: usize,
+    use_for_new_tokens: bool,
+) {
+    let dictionary = unsafe { std::slice::from_raw_parts(dictionary, len) };
+    let dictionary = gxt::Dictionary::new(dictionary).expect("Invalid dictionary");
+    if use_for_new_tokens {
+        gxt::set_compression_dictionary(Some(dictionary)).expect("Failed to use dictionary");
+    } else {
+        gxt::register_dictionary(dictionary).expect("Failed to register dictionary");
+    }
+}
+
 /// This function must be used to free returned strings after they are used.
 ///
 /// # Safety
@@ -62,6 +62,8 @@ namespace Gxt
         public PayloadKind kind;
         public T payload;
         public string? parent;
+        public ulong? created_at;
+        public ulong? expires_at;
         public string id;
         public string signature;
     }
@@ -8,6 +8,16 @@ pub fn make_key() -> FnResult<String> {
     Ok(gxt::make_key())
 }
 
+#[plugin_fn]
+pub fn key_to_recovery_phrase(key: String) -> FnResult<String> {
+    Ok(gxt::key_to_recovery_phrase(&key)?)
+}
+
+#[plugin_fn]
+pub fn key_from_recovery_phrase(phrase: String) -> FnResult<String> {
+    Ok(gxt::key_from_recovery_phrase(&phrase)?)
+}
+
 #[plugin_fn]
 pub fn make_id_card(Json(IdCardRequest { key, meta }): Json<IdCardRequest>) -> FnResult<String> {
     Ok(gxt::make_id_card(&key, meta)?)
@@ -18,6 +28,16 @@ pub fn verify_message(msg: String) -> FnResult<Json<Envelope>> {
     Ok(Json(gxt::verify_message::<serde_json::Value>(&msg)?.into()))
 }
 
+#[plugin_fn]
+pub fn verify_many(Json(tokens): Json<Vec<String>>) -> FnResult<Json<Vec<VerifyResult>>> {
+    Ok(Json(
+        gxt::verify_many::<serde_json::Value, _>(&tokens)
+            .into_iter()
+            .map(VerifyResult::from)
+            .collect(),
+    ))
+}
+
 #[plugin_fn]
 pub fn encrypt_message(
     Json(EncryptRequest {
@@ -38,3 +58,51 @@ pub fn decrypt_message(
         gxt::decrypt_message::<serde_json::Value>(&message, &key)?.into(),
     ))
 }
+
+#[plugin_fn]
+pub fn convert_token(
+    Json(ConvertRequest { token, encoding }): Json<ConvertRequest>,
+) -> FnResult<String> {
+    Ok(gxt::convert_token(&token, encoding.parse()?)?)
+}
+
+#[plugin_fn]
+pub fn split_into_parts(
+    Json(SplitRequest {
+        token,
+        max_part_len,
+    }): Json<SplitRequest>,
+) -> FnResult<Json<Vec<String>>> {
+    Ok(Json(gxt::split_into_parts(&token, max_part_len)?))
+}
+
+#[plugin_fn]
+pub fn join_parts(Json(parts): Json<Vec<String>>) -> FnResult<String> {
+    Ok(gxt::join_parts(&parts)?)
+}
+
+#[plugin_fn]
+pub fn extract_tokens(text: String) -> FnResult<Json<Vec<String>>> {
+    Ok(Json(
+        gxt::extract_tokens(&text)
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+    ))
+}
+
+#[plugin_fn]
+pub fn register_dictionary(
+    Json(DictionaryRequest {
+        dictionary,
+        use_for_new_tokens,
+    }): Json<DictionaryRequest>,
+) -> FnResult<()> {
+    let dictionary = gxt::Dictionary::new(&hex::decode(dictionary)?)?;
+    if use_for_new_tokens {
+        gxt::set_compression_dictionary(Some(dictionary))?;
+    } else {
+        gxt::register_dictionary(dictionary)?;
+    }
+    Ok(())
+}
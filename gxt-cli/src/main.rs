@@ -2,6 +2,7 @@ use std::{
     fs,
     io::{self, Read, Write},
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
 
 use anyhow::Result;
@@ -35,6 +36,45 @@ enum TimelockType {
     Private,
 }
 
+#[derive(Clone, ValueEnum)]
+enum RevocationReason {
+    Unspecified,
+    Compromised,
+    Superseded,
+    Retired,
+}
+
+impl From<RevocationReason> for gxt::RevocationReason {
+    fn from(value: RevocationReason) -> Self {
+        match value {
+            RevocationReason::Unspecified => Self::Unspecified,
+            RevocationReason::Compromised => Self::Compromised,
+            RevocationReason::Superseded => Self::Superseded,
+            RevocationReason::Retired => Self::Retired,
+        }
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+enum Encoding {
+    Base58,
+    Qr,
+    #[value(name = "base64url")]
+    Base64Url,
+    Bech32,
+}
+
+impl From<Encoding> for gxt::Encoding {
+    fn from(value: Encoding) -> Self {
+        match value {
+            Encoding::Base58 => Self::Base58,
+            Encoding::Qr => Self::Qr,
+            Encoding::Base64Url => Self::Base64Url,
+            Encoding::Bech32 => Self::Bech32,
+        }
+    }
+}
+
 #[derive(Subcommand)]
 enum Cmd {
     /// Generates a new private key
@@ -42,6 +82,40 @@ enum Cmd {
         /// Where to store the key
         #[arg(short, long)]
         out: PathBuf,
+
+        /// Protect the key with a password, which is asked for interactively
+        #[arg(long)]
+        password: bool,
+    },
+
+    /// Sets, changes or removes the password of a key
+    Passwd {
+        /// The key to update in place
+        key: PathBuf,
+
+        /// Remove the password and store the key unencrypted
+        #[arg(long)]
+        remove: bool,
+    },
+
+    /// Backs up or restores a key using a recovery phrase
+    Key {
+        #[command(subcommand)]
+        cmd: KeyCmd,
+    },
+
+    /// Creates a revocation certificate, which permanently revokes a key
+    Revoke {
+        /// The key to revoke
+        key: PathBuf,
+
+        /// Why the key is revoked
+        #[arg(short, long, value_enum, default_value = "unspecified")]
+        reason: RevocationReason,
+
+        /// Where to store the revocation certificate
+        #[arg(short, long)]
+        out: Option<PathBuf>,
     },
 
     /// Generate an ID card containing the data about a peer
@@ -63,6 +137,76 @@ enum Cmd {
         #[clap(flatten)]
         msg: MsgInput,
 
+        /// A revocation certificate. Messages signed by revoked keys are rejected. Can be repeated
+        #[arg(long)]
+        revoked: Vec<PathBuf>,
+
+        /// Print output as json
+        #[arg(short, long)]
+        json: bool,
+    },
+
+    /// Re-encode a token for QR codes, URLs or reading it out loud, without signing it again
+    Convert {
+        #[clap(flatten)]
+        msg: MsgInput,
+
+        /// The encoding of the new token
+        #[arg(short, long)]
+        encoding: Encoding,
+
+        /// Where to store the converted token
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Split a token into numbered parts for chats that limit the length of a message
+    Split {
+        #[clap(flatten)]
+        msg: MsgInput,
+
+        /// The maximum length of a part
+        #[arg(short = 'l', long, default_value_t = 2000)]
+        max_len: usize,
+
+        /// Where to store the parts, one per line
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Join the parts of a token, which can be given in any order
+    Join {
+        /// Files containing parts, one per line. Pass - to read from stdin
+        #[arg(required = true)]
+        parts: Vec<PathBuf>,
+
+        /// Where to store the joined token
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Create a detached signature over a file
+    Sign {
+        /// The file to sign
+        file: PathBuf,
+
+        /// The key of the signer
+        #[arg(short, long)]
+        key: PathBuf,
+
+        /// Where to store the signature token
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Verify a detached signature over a file
+    VerifyFile {
+        /// The file that was signed
+        file: PathBuf,
+
+        /// The signature token of the file
+        signature: PathBuf,
+
         /// Print output as json
         #[arg(short, long)]
         json: bool,
@@ -74,14 +218,49 @@ enum Cmd {
         #[arg(short, long)]
         key: PathBuf,
 
-        /// The id card of the recipient
+        /// The id card of the recipient, or a public message to reply to. Repeat to send the message to multiple recipients
+        #[arg(short, long, required = true)]
+        to: Vec<PathBuf>,
+
+        /// The parent of this message
+        #[arg(long)]
+        parent: Option<String>,
+
+        /// How long the message stays valid, e.g. 90s, 30m, 12h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        expires_in: Option<Duration>,
+
+        /// Use a new key for this message, so it stays secret even if the sender's key leaks
+        #[arg(long)]
+        forward_secret: bool,
+
+        /// Also mix the keys of the sender and the recipient into the forward secret key
+        #[arg(long, requires = "forward_secret")]
+        static_static: bool,
+
+        /// The payload of the message. Can be anything, but must be set. Pass - to read from stdin
         #[arg(short, long)]
-        to: PathBuf,
+        payload: String,
+
+        /// Where to store the message token
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Create a signed public message, which anyone can read
+    Post {
+        /// The key of the author
+        #[arg(short, long)]
+        key: PathBuf,
 
         /// The parent of this message
         #[arg(long)]
         parent: Option<String>,
 
+        /// How long the message stays valid, e.g. 90s, 30m, 12h or 7d
+        #[arg(long, value_parser = parse_duration)]
+        expires_in: Option<Duration>,
+
         /// The payload of the message. Can be anything, but must be set. Pass - to read from stdin
         #[arg(short, long)]
         payload: String,
@@ -100,6 +279,10 @@ enum Cmd {
         #[clap(flatten)]
         msg: MsgInput,
 
+        /// A revocation certificate. Messages signed by revoked keys are rejected. Can be repeated
+        #[arg(long)]
+        revoked: Vec<PathBuf>,
+
         /// Print output as json
         #[arg(short, long)]
         json: bool,
@@ -115,30 +298,176 @@ enum Cmd {
     },
 }
 
+#[derive(Subcommand)]
+enum KeyCmd {
+    /// Prints the recovery phrase of a key. Write it down and keep it somewhere safe
+    Backup {
+        /// The key to back up
+        key: PathBuf,
+
+        /// Where to store the recovery phrase
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+
+    /// Restores a key from its recovery phrase
+    Restore {
+        /// The recovery phrase. Pass - to read from stdin
+        #[arg(short, long)]
+        phrase: String,
+
+        /// Where to store the key
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Protect the key with a password, which is asked for interactively
+        #[arg(long)]
+        password: bool,
+    },
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.cmd {
-        Cmd::Keygen { out } => {
-            let signing_key = gxt::make_key();
+        Cmd::Keygen { out, password } => {
+            let signing_key = if password {
+                gxt::make_key_with_password(&prompt_new_password()?)?
+            } else {
+                gxt::make_key()
+            };
             write_out_string(&signing_key, Some(out.as_ref()))?;
         }
 
+        Cmd::Passwd { key, remove } => {
+            let signing_key = fs::read_to_string(&key)?;
+            let signing_key = match (is_locked(&signing_key), remove) {
+                (true, true) => gxt::unlock_key(&signing_key, &prompt_password(&key)?)?,
+                (true, false) => gxt::change_key_password(
+                    &signing_key,
+                    &prompt_password(&key)?,
+                    &prompt_new_password()?,
+                )?,
+                (false, true) => anyhow::bail!("The key is not protected by a password"),
+                (false, false) => gxt::lock_key(&signing_key, &prompt_new_password()?)?,
+            };
+            write_out_string(&signing_key, Some(key.as_ref()))?;
+        }
+
+        Cmd::Key {
+            cmd: KeyCmd::Backup { key, out },
+        } => {
+            let signing_key = read_key(&key)?;
+            let phrase = gxt::key_to_recovery_phrase(&signing_key)?;
+            write_out_string(&format!("{phrase}\n"), out.as_deref())?;
+        }
+
+        Cmd::Key {
+            cmd:
+                KeyCmd::Restore {
+                    phrase,
+                    out,
+                    password,
+                },
+        } => {
+            let phrase = value_or_stdin(&phrase)?;
+            let signing_key = gxt::key_from_recovery_phrase(&phrase)?;
+            let signing_key = if password {
+                gxt::lock_key(&signing_key, &prompt_new_password()?)?
+            } else {
+                signing_key
+            };
+            write_out_string(&signing_key, Some(out.as_ref()))?;
+        }
+
+        Cmd::Revoke { key, reason, out } => {
+            let signing_key = read_key(&key)?;
+            let revocation = gxt::make_revocation(&signing_key, reason.into())?;
+            write_out_string(&revocation, out.as_deref())?;
+        }
+
         Cmd::Id { out, key, meta } => {
-            let signing_key = fs::read_to_string(key)?;
+            let signing_key = read_key(&key)?;
             let meta_json = value_or_stdin(&meta)?;
             let meta = gxt::value_from_str(meta_json.trim())?;
             let id_card = gxt::make_id_card(&signing_key, meta)?;
             write_out_string(&id_card, out.as_deref())?;
         }
 
-        Cmd::Verify { msg, json } => {
+        Cmd::Verify { msg, revoked, json } => {
             let token = match (msg.msg, msg.file) {
                 (Some(msg), None) => value_or_stdin(&msg)?,
                 (None, Some(file)) => fs::read_to_string(file)?,
                 _ => anyhow::bail!("Nothing to verify"),
             };
-            let envelope = gxt::verify_message::<gxt::JsonValue>(&token)?;
+            let revocations = read_revocations(&revoked)?;
+            let envelope =
+                gxt::verify_message_with_revocations::<gxt::JsonValue>(&token, &revocations)?;
+            let now = unix_now()?;
+            if envelope.is_expired_at(now) {
+                anyhow::bail!(gxt::GxtError::Expired);
+            }
+            if envelope.is_not_yet_valid_at(now) {
+                anyhow::bail!(gxt::GxtError::NotYetValid);
+            }
+            if json {
+                println!("{}", gxt::to_json_pretty(&envelope)?);
+            } else {
+                println!("{envelope}");
+            }
+        }
+
+        Cmd::Convert { msg, encoding, out } => {
+            let token = match (msg.msg, msg.file) {
+                (Some(msg), None) => value_or_stdin(&msg)?,
+                (None, Some(file)) => fs::read_to_string(file)?,
+                _ => anyhow::bail!("Nothing to convert"),
+            };
+            let token = gxt::convert_token(&token, encoding.into())?;
+            write_out_string(&token, out.as_deref())?;
+        }
+
+        Cmd::Split { msg, max_len, out } => {
+            let token = match (msg.msg, msg.file) {
+                (Some(msg), None) => value_or_stdin(&msg)?,
+                (None, Some(file)) => fs::read_to_string(file)?,
+                _ => anyhow::bail!("Nothing to split"),
+            };
+            let parts = gxt::split_into_parts(&token, max_len)?;
+            write_out_string(&(parts.join("\n") + "\n"), out.as_deref())?;
+        }
+
+        Cmd::Join { parts, out } => {
+            let mut token_parts = gxt::TokenParts::new();
+            for path in parts {
+                let content = if path == Path::new("-") {
+                    value_or_stdin("-")?
+                } else {
+                    fs::read_to_string(path)?
+                };
+                for part in content.lines().filter(|line| !line.trim().is_empty()) {
+                    token_parts.insert(part)?;
+                }
+            }
+            write_out_string(&token_parts.assemble()?.to_string(), out.as_deref())?;
+        }
+
+        Cmd::Sign { file, key, out } => {
+            let signing_key = read_key(&key)?;
+            let signature = signing_key
+                .parse::<gxt::Identity>()?
+                .sign_reader(fs::File::open(file)?)?;
+            write_out_string(&signature.to_string(), out.as_deref())?;
+        }
+
+        Cmd::VerifyFile {
+            file,
+            signature,
+            json,
+        } => {
+            let signature = fs::read_to_string(signature)?.parse::<gxt::DetachedSignature>()?;
+            signature.verify_reader(fs::File::open(file)?)?;
+            let envelope = signature.token().verify::<gxt::JsonValue>()?;
             if json {
                 println!("{}", gxt::to_json_pretty(&envelope)?);
             } else {
@@ -150,26 +479,93 @@ fn main() -> Result<()> {
             key,
             to,
             parent,
+            expires_in,
+            forward_secret,
+            static_static,
             payload,
             out,
         } => {
-            let signing_key = fs::read_to_string(key)?;
-            let id_card = fs::read_to_string(to)?;
+            let signing_key = read_key(&key)?;
+            let id_cards = to
+                .iter()
+                .map(fs::read_to_string)
+                .collect::<Result<Vec<_>, _>>()?;
             let payload_json = value_or_stdin(&payload)?;
             let payload = gxt::value_from_str(payload_json.trim())?;
-            let encrypted_message = gxt::encrypt_message(&signing_key, &id_card, &payload, parent)?;
+            let validity = match expires_in {
+                Some(lifetime) => gxt::Validity::expires_in(unix_now()?, lifetime),
+                None => gxt::Validity::default(),
+            };
+            let agreement = match (forward_secret, static_static) {
+                (false, _) => gxt::KeyAgreement::Static,
+                (true, false) => gxt::KeyAgreement::Ephemeral,
+                (true, true) => gxt::KeyAgreement::EphemeralStatic,
+            };
+            let encrypted_message = match id_cards.as_slice() {
+                [id_card] => gxt::encrypt_message_with_key_agreement(
+                    &signing_key,
+                    id_card,
+                    &payload,
+                    parent,
+                    validity,
+                    agreement,
+                )?,
+                id_cards => gxt::encrypt_message_for_many_with_key_agreement(
+                    &signing_key,
+                    &id_cards.iter().map(String::as_str).collect::<Vec<_>>(),
+                    &payload,
+                    parent,
+                    validity,
+                    agreement,
+                )?,
+            };
             write_out_string(&encrypted_message, out.as_deref())?;
         }
 
-        Cmd::Decrypt { key, msg, json } => {
+        Cmd::Post {
+            key,
+            parent,
+            expires_in,
+            payload,
+            out,
+        } => {
+            let signing_key = read_key(&key)?;
+            let payload_json = value_or_stdin(&payload)?;
+            let payload = gxt::value_from_str(payload_json.trim())?;
+            let validity = match expires_in {
+                Some(lifetime) => gxt::Validity::expires_in(unix_now()?, lifetime),
+                None => gxt::Validity::default(),
+            };
+            let public_message =
+                gxt::make_public_message_with_validity(&signing_key, &payload, parent, validity)?;
+            write_out_string(&public_message, out.as_deref())?;
+        }
+
+        Cmd::Decrypt {
+            key,
+            msg,
+            revoked,
+            json,
+        } => {
             let encrypted_message = match (msg.msg, msg.file) {
                 (Some(msg), None) => value_or_stdin(&msg)?,
                 (None, Some(file)) => fs::read_to_string(file)?,
                 _ => anyhow::bail!("Nothing to verify"),
             };
-            let signing_key = fs::read_to_string(key)?;
-            let envelope =
-                gxt::decrypt_message::<gxt::JsonValue>(&encrypted_message, &signing_key)?;
+            let signing_key = read_key(&key)?;
+            let revocations = read_revocations(&revoked)?;
+            let envelope = gxt::decrypt_message_with_revocations::<gxt::JsonValue>(
+                &encrypted_message,
+                &signing_key,
+                &revocations,
+            )?;
+            let now = unix_now()?;
+            if envelope.is_expired_at(now) {
+                anyhow::bail!(gxt::GxtError::Expired);
+            }
+            if envelope.is_not_yet_valid_at(now) {
+                anyhow::bail!(gxt::GxtError::NotYetValid);
+            }
             if json {
                 println!("{}", gxt::to_json_pretty(&envelope)?);
             } else {
@@ -184,6 +580,46 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Reads a key from a file and asks for its password, if it is protected by one.
+fn read_key(path: &Path) -> Result<String> {
+    let key = fs::read_to_string(path)?;
+    if is_locked(&key) {
+        Ok(gxt::unlock_key(&key, &prompt_password(path)?)?)
+    } else {
+        Ok(key)
+    }
+}
+
+fn read_revocations(paths: &[PathBuf]) -> Result<gxt::Revocations> {
+    paths
+        .iter()
+        .map(|path| Ok(fs::read_to_string(path)?.parse::<gxt::Revocation>()?))
+        .collect()
+}
+
+fn is_locked(key: &str) -> bool {
+    key.parse::<gxt::Token>()
+        .is_ok_and(|token| token.kind() == gxt::PayloadKind::EncryptedKey)
+}
+
+fn prompt_password(path: &Path) -> Result<String> {
+    Ok(rpassword::prompt_password(format!(
+        "Password for {}: ",
+        path.display()
+    ))?)
+}
+
+fn prompt_new_password() -> Result<String> {
+    let password = rpassword::prompt_password("New password: ")?;
+    if password.is_empty() {
+        anyhow::bail!("The password must not be empty");
+    }
+    if password != rpassword::prompt_password("Repeat password: ")? {
+        anyhow::bail!("The passwords do not match");
+    }
+    Ok(password)
+}
+
 fn write_out_string(s: &str, path: Option<&Path>) -> Result<()> {
     write_out_bytes(s.as_bytes(), path)
 }
@@ -209,3 +645,30 @@ fn value_or_stdin(payload: &str) -> Result<String> {
         Ok(payload.to_string())
     }
 }
+
+fn unix_now() -> Result<u64> {
+    Ok(SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs())
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration: {s}"))?;
+    let factor = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return Err(format!("unknown duration unit: {unit}")),
+    };
+    amount
+        .checked_mul(factor)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration too long: {s}"))
+}
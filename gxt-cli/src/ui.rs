@@ -30,6 +30,8 @@ impl From<gxt::Envelope<gxt::JsonValue>> for UiEnvelope {
             kind,
             payload,
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         } = value;
@@ -39,6 +41,8 @@ impl From<gxt::Envelope<gxt::JsonValue>> for UiEnvelope {
             id: id.into(),
             kind: kind.to_shared_string(),
             parent: parent.unwrap_or_default().into(),
+            created_at: created_at.map(|t| t.to_string()).unwrap_or_default().into(),
+            expires_at: expires_at.map(|t| t.to_string()).unwrap_or_default().into(),
             payload: gxt::to_json_pretty(&payload).unwrap().into(),
             signature: signature.into(),
             verification_key: verification_key.into(),
@@ -62,10 +66,12 @@ pub fn run(path: Option<PathBuf>, key: Option<PathBuf>) -> anyhow::Result<()> {
             kind,
             payload,
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         } = if let Some(key) = key {
-            let key = std::fs::read_to_string(key)?;
+            let key = crate::read_key(&key)?;
             gxt::decrypt_message::<gxt::JsonValue>(&text, &key)?
         } else {
             let envelope = gxt::verify_message::<gxt::JsonValue>(&text)?;
@@ -80,6 +86,8 @@ pub fn run(path: Option<PathBuf>, key: Option<PathBuf>) -> anyhow::Result<()> {
             id: id.into(),
             kind: kind.to_shared_string(),
             parent: parent.unwrap_or_default().into(),
+            created_at: created_at.map(|t| t.to_string()).unwrap_or_default().into(),
+            expires_at: expires_at.map(|t| t.to_string()).unwrap_or_default().into(),
             payload: gxt::to_json_pretty(&payload)?.into(),
             signature: signature.into(),
             verification_key: verification_key.into(),
@@ -109,7 +117,7 @@ pub fn run(path: Option<PathBuf>, key: Option<PathBuf>) -> anyhow::Result<()> {
             if let Some(file) = rfd::FileDialog::new().pick_file() {
                 let ui = ui_handle.unwrap();
 
-                let key = std::fs::read_to_string(file).unwrap();
+                let key = crate::read_key(&file).unwrap();
                 let envelope =
                     gxt::decrypt_message::<gxt::JsonValue>(&ui.get_token_text(), &key).unwrap();
                 ui.set_envelope(envelope.into());
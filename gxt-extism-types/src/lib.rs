@@ -16,6 +16,11 @@ pub enum PayloadKind {
     Id,
     Msg,
     Key,
+    EncryptedKey,
+    Rotation,
+    Revocation,
+    Public,
+    Signature,
 }
 
 impl From<gxt::PayloadKind> for PayloadKind {
@@ -24,6 +29,11 @@ impl From<gxt::PayloadKind> for PayloadKind {
             gxt::PayloadKind::Id => PayloadKind::Id,
             gxt::PayloadKind::Msg => PayloadKind::Msg,
             gxt::PayloadKind::Key => PayloadKind::Key,
+            gxt::PayloadKind::EncryptedKey => PayloadKind::EncryptedKey,
+            gxt::PayloadKind::Rotation => PayloadKind::Rotation,
+            gxt::PayloadKind::Revocation => PayloadKind::Revocation,
+            gxt::PayloadKind::Public => PayloadKind::Public,
+            gxt::PayloadKind::Signature => PayloadKind::Signature,
         }
     }
 }
@@ -37,6 +47,8 @@ pub struct Envelope {
     pub kind: PayloadKind,
     pub payload: serde_json::Value,
     pub parent: Option<String>,
+    pub created_at: Option<u64>,
+    pub expires_at: Option<u64>,
     pub id: String,
     pub signature: String,
 }
@@ -50,6 +62,8 @@ impl<P: Serialize + DeserializeOwned> From<gxt::Envelope<P>> for Envelope {
             kind,
             payload,
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         }: gxt::Envelope<P>,
@@ -62,12 +76,39 @@ impl<P: Serialize + DeserializeOwned> From<gxt::Envelope<P>> for Envelope {
             payload: serde_json::to_value(payload)
                 .expect("Could not convert payload from JSON to CBOR"),
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         }
     }
 }
 
+/// The result of one token of [`calls::VERIFY_MANY`], either the envelope or the error.
+#[derive(Clone, Debug, FromBytes, Deserialize, Serialize, ToBytes)]
+#[encoding(Json)]
+pub struct VerifyResult {
+    pub envelope: Option<Envelope>,
+    pub error: Option<String>,
+}
+
+impl<P: Serialize + DeserializeOwned> From<Result<gxt::Envelope<P>, gxt::GxtError>>
+    for VerifyResult
+{
+    fn from(result: Result<gxt::Envelope<P>, gxt::GxtError>) -> Self {
+        match result {
+            Ok(envelope) => VerifyResult {
+                envelope: Some(envelope.into()),
+                error: None,
+            },
+            Err(e) => VerifyResult {
+                envelope: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug, FromBytes, Deserialize, Serialize, ToBytes)]
 #[encoding(Json)]
 pub struct EncryptRequest {
@@ -84,12 +125,38 @@ pub struct DecryptRequest {
     pub key: String,
 }
 
+#[derive(Clone, Debug, FromBytes, Deserialize, Serialize, ToBytes)]
+#[encoding(Json)]
+pub struct ConvertRequest {
+    pub token: String,
+    pub encoding: String,
+}
+
+#[derive(Clone, Debug, FromBytes, Deserialize, Serialize, ToBytes)]
+#[encoding(Json)]
+pub struct SplitRequest {
+    pub token: String,
+    pub max_part_len: usize,
+}
+
+#[derive(Clone, Debug, FromBytes, Deserialize, Serialize, ToBytes)]
+#[encoding(Json)]
+pub struct DictionaryRequest {
+    /// The hex encoded zstd dictionary
+    pub dictionary: String,
+    pub use_for_new_tokens: bool,
+}
+
 #[allow(non_camel_case_types)]
 pub mod calls {
+    use crate::ConvertRequest;
     use crate::DecryptRequest;
+    use crate::DictionaryRequest;
     use crate::EncryptRequest;
     use crate::Envelope;
     use crate::IdCardRequest;
+    use crate::SplitRequest;
+    use crate::VerifyResult;
 
     pub const MAKE_KEY: &str = "make_key";
     pub type MAKE_KEY_IN = ();
@@ -103,6 +170,10 @@ pub mod calls {
     pub type VERIFY_MESSAGE_IN = String;
     pub type VERIFY_MESSAGE_OUT = Envelope;
 
+    pub const VERIFY_MANY: &str = "verify_many";
+    pub type VERIFY_MANY_IN = Vec<String>;
+    pub type VERIFY_MANY_OUT = Vec<VerifyResult>;
+
     pub const ENCRYPT_MESSAGE: &str = "encrypt_message";
     pub type ENCRYPT_MESSAGE_IN = EncryptRequest;
     pub type ENCRYPT_MESSAGE_OUT = String;
@@ -110,4 +181,24 @@ pub mod calls {
     pub const DECRYPT_MESSAGE: &str = "decrypt_message";
     pub type DECRYPT_MESSAGE_IN = DecryptRequest;
     pub type DECRYPT_MESSAGE_OUT = Envelope;
+
+    pub const CONVERT_TOKEN: &str = "convert_token";
+    pub type CONVERT_TOKEN_IN = ConvertRequest;
+    pub type CONVERT_TOKEN_OUT = String;
+
+    pub const SPLIT_INTO_PARTS: &str = "split_into_parts";
+    pub type SPLIT_INTO_PARTS_IN = SplitRequest;
+    pub type SPLIT_INTO_PARTS_OUT = Vec<String>;
+
+    pub const JOIN_PARTS: &str = "join_parts";
+    pub type JOIN_PARTS_IN = Vec<String>;
+    pub type JOIN_PARTS_OUT = String;
+
+    pub const EXTRACT_TOKENS: &str = "extract_tokens";
+    pub type EXTRACT_TOKENS_IN = String;
+    pub type EXTRACT_TOKENS_OUT = Vec<String>;
+
+    pub const REGISTER_DICTIONARY: &str = "register_dictionary";
+    pub type REGISTER_DICTIONARY_IN = DictionaryRequest;
+    pub type REGISTER_DICTIONARY_OUT = ();
 }
@@ -10,6 +10,16 @@ pub fn make_key() -> String {
     gxt::make_key()
 }
 
+#[wasm_bindgen]
+pub fn key_to_recovery_phrase(key: &str) -> Result<String, JsValue> {
+    Ok(gxt::key_to_recovery_phrase(key).map_err(|e| e.to_string())?)
+}
+
+#[wasm_bindgen]
+pub fn key_from_recovery_phrase(phrase: &str) -> Result<String, JsValue> {
+    Ok(gxt::key_from_recovery_phrase(phrase).map_err(|e| e.to_string())?)
+}
+
 #[wasm_bindgen]
 pub fn make_id_card(key: &str, meta: JsValue) -> Result<String, JsValue> {
     let meta: serde_json::Value = serde_wasm_bindgen::from_value(meta)?;
@@ -24,6 +34,16 @@ pub enum WasmPayloadKind {
     Msg,
     /// A key packaged into a gxt token
     Key,
+    /// A key packaged into a gxt token, encrypted with a password
+    EncryptedKey,
+    /// A statement that a key was replaced by a successor key
+    Rotation,
+    /// A certificate that permanently revokes a key
+    Revocation,
+    /// A signed message, which anyone can read
+    Public,
+    /// A signature over data that is not part of the token
+    Signature,
 }
 
 impl From<gxt::PayloadKind> for WasmPayloadKind {
@@ -32,6 +52,11 @@ impl From<gxt::PayloadKind> for WasmPayloadKind {
             gxt::PayloadKind::Id => Self::Id,
             gxt::PayloadKind::Msg => Self::Msg,
             gxt::PayloadKind::Key => Self::Key,
+            gxt::PayloadKind::EncryptedKey => Self::EncryptedKey,
+            gxt::PayloadKind::Rotation => Self::Rotation,
+            gxt::PayloadKind::Revocation => Self::Revocation,
+            gxt::PayloadKind::Public => Self::Public,
+            gxt::PayloadKind::Signature => Self::Signature,
         }
     }
 }
@@ -50,6 +75,10 @@ pub struct WasmEnvelope {
     pub payload: String,
     /// Id of the Parent Message
     pub parent: Option<String>,
+    /// Unix timestamp of when the message was created
+    pub created_at: Option<u64>,
+    /// Unix timestamp from which on the message is no longer valid
+    pub expires_at: Option<u64>,
     /// Id of this Message
     pub id: String,
     /// Signature of this Message
@@ -65,6 +94,8 @@ impl From<gxt::Envelope<serde_json::Value>> for WasmEnvelope {
             kind,
             payload,
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         }: gxt::Envelope<serde_json::Value>,
@@ -76,6 +107,8 @@ impl From<gxt::Envelope<serde_json::Value>> for WasmEnvelope {
             kind: kind.into(),
             payload: serde_json::to_string(&payload).unwrap(),
             parent,
+            created_at,
+            expires_at,
             id,
             signature,
         }
@@ -89,6 +122,31 @@ pub fn verify_message(msg: &str) -> Result<JsValue, JsValue> {
     Ok(serde_wasm_bindgen::to_value(&wasm_envelope)?)
 }
 
+/// The result of one token of [`verify_many`], either the envelope or the error.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WasmVerifyResult {
+    pub envelope: Option<WasmEnvelope>,
+    pub error: Option<String>,
+}
+
+#[wasm_bindgen]
+pub fn verify_many(tokens: Vec<String>) -> Result<JsValue, JsValue> {
+    let results = gxt::verify_many::<serde_json::Value, _>(&tokens)
+        .into_iter()
+        .map(|result| match result {
+            Ok(envelope) => WasmVerifyResult {
+                envelope: Some(envelope.into()),
+                error: None,
+            },
+            Err(e) => WasmVerifyResult {
+                envelope: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect::<Vec<_>>();
+    Ok(serde_wasm_bindgen::to_value(&results)?)
+}
+
 #[wasm_bindgen]
 pub fn encrypt_message(
     key: &str,
@@ -107,3 +165,38 @@ pub fn decrypt_message(message: &str, key: &str) -> Result<JsValue, JsValue> {
     let wasm_envelope: WasmEnvelope = envelope.into();
     Ok(serde_wasm_bindgen::to_value(&wasm_envelope)?)
 }
+
+#[wasm_bindgen]
+pub fn convert_token(token: &str, encoding: &str) -> Result<String, JsValue> {
+    let encoding = encoding.parse().map_err(|e: gxt::GxtError| e.to_string())?;
+    Ok(gxt::convert_token(token, encoding).map_err(|e| e.to_string())?)
+}
+
+#[wasm_bindgen]
+pub fn register_dictionary(dictionary: &[u8], use_for_new_tokens: bool) -> Result<(), JsValue> {
+    let dictionary = gxt::Dictionary::new(dictionary).map_err(|e| e.to_string())?;
+    if use_for_new_tokens {
+        gxt::set_compression_dictionary(Some(dictionary)).map_err(|e| e.to_string())?;
+    } else {
+        gxt::register_dictionary(dictionary).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn split_into_parts(token: &str, max_part_len: usize) -> Result<Vec<String>, JsValue> {
+    Ok(gxt::split_into_parts(token, max_part_len).map_err(|e| e.to_string())?)
+}
+
+#[wasm_bindgen]
+pub fn join_parts(parts: Vec<String>) -> Result<String, JsValue> {
+    Ok(gxt::join_parts(&parts).map_err(|e| e.to_string())?)
+}
+
+#[wasm_bindgen]
+pub fn extract_tokens(text: &str) -> Vec<String> {
+    gxt::extract_tokens(text)
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+}
@@ -0,0 +1,192 @@
+//! Compares the size of tokens in the hex based version 4 layout with the current layout,
+//! which stores keys, ids, signatures, nonces and ciphertexts as byte strings,
+//! and with tokens that are compressed with the built-in dictionary.
+//!
+//! Run with `cargo bench -p gxt --bench token_size`.
+
+use gxt::advisory::{AttributeModifier, IdCard, Item, ModifierKind, TradeOrder, TradeRequest};
+use gxt::{Dictionary, Identity};
+use serde_cbor::Value as CborValue;
+
+fn main() {
+    let alice = Identity::generate();
+    let bob = Identity::generate();
+    let order = trade_order();
+
+    gxt::set_compression_dictionary(None).expect("no dictionary");
+    let plain_tokens = tokens(&alice, &bob, &order);
+    gxt::set_compression_dictionary(Some(Dictionary::builtin())).expect("built-in dictionary");
+    let dictionary_tokens = tokens(&alice, &bob, &order);
+
+    println!(
+        "{:<30} {:>10} {:>10} {:>10} {:>10} {:>10} {:>8}",
+        "token", "v4 cbor", "v8 cbor", "v4 token", "v8 token", "v8 + dict", "saved"
+    );
+    for ((name, token), (_, dictionary_token)) in plain_tokens.into_iter().zip(dictionary_tokens) {
+        let (prefix, current) = decode(&token);
+        let legacy = to_version_4(current.clone());
+        let legacy_token = encode(&prefix, &legacy);
+        println!(
+            "{:<30} {:>10} {:>10} {:>10} {:>10} {:>10} {:>7.1}%",
+            name,
+            cbor_len(&legacy),
+            cbor_len(&current),
+            legacy_token.len(),
+            token.len(),
+            dictionary_token.len(),
+            100.0 * (1.0 - dictionary_token.len() as f64 / legacy_token.len() as f64),
+        );
+    }
+}
+
+/// Creates the compared tokens with the current compression dictionary.
+fn tokens(alice: &Identity, bob: &Identity, order: &TradeOrder) -> Vec<(&'static str, String)> {
+    let id_card = bob
+        .make_id_card(IdCard {
+            display_name: "Bob".into(),
+            data: None,
+        })
+        .expect("id card");
+    vec![
+        ("id card", id_card.to_string()),
+        (
+            "public trade order",
+            alice
+                .make_public_message(order, None)
+                .expect("public message")
+                .to_string(),
+        ),
+        (
+            "encrypted trade order",
+            alice
+                .encrypt_message(&id_card, order, None)
+                .expect("encrypted message")
+                .to_string(),
+        ),
+        (
+            "encrypted trade order for 3",
+            alice
+                .encrypt_message_for_many(
+                    &[
+                        id_card.clone(),
+                        id_card_of_stranger(),
+                        id_card_of_stranger(),
+                    ],
+                    order,
+                    None,
+                )
+                .expect("encrypted message")
+                .to_string(),
+        ),
+    ]
+}
+
+fn trade_order() -> TradeOrder {
+    let sword = Item {
+        id: "sword_of_dawn".into(),
+        display_name: Some("Sword of Dawn".into()),
+        description: Some("Glows faintly when enemies are near.".into()),
+        attributes: vec![
+            AttributeModifier {
+                id: "strength".into(),
+                display_name: Some("Strength".into()),
+                amount: 12,
+                kind: ModifierKind::Flat,
+                data: None,
+            },
+            AttributeModifier {
+                id: "crit_chance".into(),
+                display_name: Some("Critical Chance".into()),
+                amount: 5,
+                kind: ModifierKind::Percent,
+                data: None,
+            },
+        ],
+        amount: 1,
+        data: None,
+    };
+    let gold = Item {
+        id: "gold".into(),
+        display_name: Some("Gold".into()),
+        amount: 2500,
+        ..Item::default()
+    };
+    let potion = Item {
+        id: "health_potion".into(),
+        display_name: Some("Health Potion".into()),
+        amount: 10,
+        ..Item::default()
+    };
+    TradeOrder {
+        requests: vec![
+            TradeRequest {
+                id: "req-1".into(),
+                wanted: vec![sword],
+                offered: vec![gold.clone()],
+                data: None,
+            },
+            TradeRequest {
+                id: "req-2".into(),
+                wanted: vec![potion],
+                offered: vec![Item {
+                    amount: 300,
+                    ..gold
+                }],
+                data: None,
+            },
+        ],
+        all_or_nothing: false,
+        note: Some("Meet me in the tavern".into()),
+    }
+}
+
+fn id_card_of_stranger() -> gxt::IdCard {
+    Identity::generate()
+        .make_id_card(IdCard::default())
+        .expect("id card")
+}
+
+fn decode(token: &str) -> (String, CborValue) {
+    let (prefix, body) = token.split_at(token.find(':').expect("prefix") + 1);
+    let compressed = bs58::decode(body).into_vec().expect("base58");
+    let raw = zstd::decode_all(&compressed[..]).expect("zstd");
+    (
+        prefix.to_string(),
+        serde_cbor::from_slice(&raw).expect("cbor"),
+    )
+}
+
+fn encode(prefix: &str, value: &CborValue) -> String {
+    let raw = serde_cbor::to_vec(value).expect("cbor");
+    let compressed = zstd::encode_all(&raw[..], 3).expect("zstd");
+    format!("{prefix}{}", bs58::encode(compressed).into_string())
+}
+
+fn cbor_len(value: &CborValue) -> usize {
+    serde_cbor::to_vec(value).expect("cbor").len()
+}
+
+/// Rewrites an envelope into the version 4 layout, which has no validity
+/// and stores all binary data as hex encoded text.
+fn to_version_4(envelope: CborValue) -> CborValue {
+    let CborValue::Array(mut values) = hex_fields(envelope) else {
+        panic!("envelope is not an array");
+    };
+    values[0] = CborValue::Integer(4);
+    // created_at and expires_at
+    values.drain(5..7);
+    CborValue::Array(values)
+}
+
+fn hex_fields(value: CborValue) -> CborValue {
+    match value {
+        CborValue::Bytes(bytes) => CborValue::Text(hex::encode(bytes)),
+        CborValue::Array(values) => CborValue::Array(values.into_iter().map(hex_fields).collect()),
+        CborValue::Map(map) => CborValue::Map(
+            map.into_iter()
+                .map(|(key, value)| (key, hex_fields(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
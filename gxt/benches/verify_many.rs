@@ -0,0 +1,178 @@
+//! Compares verifying tokens one by one with [`gxt::verify_message`] against [`gxt::verify_many`],
+//! which decodes and verifies them in parallel,
+//! with all tokens valid and with one bad signature in every 100 tokens.
+//!
+//! It also prints the cost of one signature with Ed25519 batch verification,
+//! which `verify_many` does not use: batch verification can accept signatures that strict verification rejects,
+//! so the signatures of an accepted batch would have to be verified strictly as well.
+//!
+//! Run with `cargo bench -p gxt --bench verify_many`.
+
+use std::time::{Duration, Instant};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use gxt::{Identity, JsonValue, json};
+
+const SIGNERS: usize = 32;
+/// Number of signatures that are verified together with batch verification.
+const BATCH_SIZE: usize = 32;
+const COUNTS: [usize; 4] = [16, 256, 1024, 4096];
+/// Every benchmark runs at least this long, to smooth out the measurements.
+const MIN_DURATION: Duration = Duration::from_millis(500);
+
+fn main() {
+    let signers = (0..SIGNERS)
+        .map(|_| Identity::generate())
+        .collect::<Vec<_>>();
+    let valid = (0..*COUNTS.iter().max().expect("counts"))
+        .map(|i| token(&signers[i % SIGNERS], i))
+        .collect::<Vec<_>>();
+    let mut with_bad = valid.clone();
+    for i in (50..with_bad.len()).step_by(100) {
+        // a token of a different message, whose signature does not match
+        with_bad[i] = forged(&valid[i], &valid[i - 1]);
+    }
+
+    println!(
+        "{:<8} {:<12} {:>12} {:>12} {:>8}",
+        "tokens", "signatures", "loop", "verify_many", "speedup"
+    );
+    for count in COUNTS {
+        for (name, tokens) in [("valid", &valid), ("1% bad", &with_bad)] {
+            let tokens = &tokens[..count];
+            let sequential = measure(|| {
+                tokens
+                    .iter()
+                    .filter(|token| gxt::verify_message::<JsonValue>(token).is_ok())
+                    .count()
+            });
+            let batched = measure(|| {
+                gxt::verify_many::<JsonValue, _>(tokens)
+                    .iter()
+                    .filter(|result| result.is_ok())
+                    .count()
+            });
+            println!(
+                "{:<8} {:<12} {:>10.2}ms {:>10.2}ms {:>7.1}x",
+                count,
+                name,
+                sequential.as_secs_f64() * 1000.0,
+                batched.as_secs_f64() * 1000.0,
+                sequential.as_secs_f64() / batched.as_secs_f64(),
+            );
+        }
+    }
+    signatures();
+}
+
+/// Prints the cost of one signature with strict, batch, and batch followed by strict verification.
+fn signatures() {
+    let signatures = (0..BATCH_SIZE * 32)
+        .map(|i| {
+            let key = SigningKey::from_bytes(&[i as u8; 32]);
+            let message = format!("message {i}").into_bytes();
+            let signature = key.sign(&message);
+            (key.verifying_key(), message, signature)
+        })
+        .collect::<Vec<_>>();
+    let strict = |(key, message, signature): &(VerifyingKey, Vec<u8>, Signature)| {
+        key.verify_strict(message, signature).is_ok()
+    };
+    let batch = |batch: &[(VerifyingKey, Vec<u8>, Signature)]| {
+        let messages = batch
+            .iter()
+            .map(|(_, m, _)| m.as_slice())
+            .collect::<Vec<_>>();
+        let signatures = batch.iter().map(|(_, _, s)| *s).collect::<Vec<_>>();
+        let keys = batch.iter().map(|(k, _, _)| *k).collect::<Vec<_>>();
+        ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok()
+    };
+    let per_signature =
+        |f: &dyn Fn() -> usize| measure(f).as_secs_f64() * 1_000_000.0 / signatures.len() as f64;
+
+    println!();
+    println!("{:<24} {:>16}", "signature check", "per signature");
+    for (name, time) in [
+        (
+            "strict",
+            per_signature(&|| signatures.iter().filter(|s| strict(s)).count()),
+        ),
+        (
+            "batch",
+            per_signature(&|| {
+                signatures
+                    .chunks(BATCH_SIZE)
+                    .filter(|chunk| batch(chunk))
+                    .count()
+            }),
+        ),
+        (
+            "batch, then strict",
+            per_signature(&|| {
+                signatures
+                    .chunks(BATCH_SIZE)
+                    .filter(|chunk| batch(chunk))
+                    .map(|chunk| chunk.iter().filter(|s| strict(s)).count())
+                    .sum()
+            }),
+        ),
+    ] {
+        println!("{name:<24} {time:>14.2}µs");
+    }
+}
+
+fn token(signer: &Identity, i: usize) -> String {
+    signer
+        .message()
+        .payload(&json!({
+            "requests": [{
+                "id": format!("req-{i}"),
+                "wanted": [{"id": "gold", "amount": 100 + i}],
+                "offered": [{"id": "iron_sword", "display_name": "Iron Sword", "amount": 1}],
+            }],
+            "all_or_nothing": true,
+        }))
+        .sign()
+        .expect("public message")
+        .to_string()
+}
+
+/// Puts the signature of `other` into `token` and fixes the id, so only the signature is wrong.
+fn forged(token: &str, other: &str) -> String {
+    let (prefix, mut values) = decode(token);
+    let (_, other_values) = decode(other);
+    let len = values.len();
+    values[len - 1] = other_values[len - 1].clone();
+    let raw = serde_cbor::to_vec(&serde_cbor::Value::Array(values)).expect("cbor");
+    format!(
+        "{prefix}{}",
+        bs58::encode(zstd::encode_all(&raw[..], 3).expect("compress")).into_string()
+    )
+}
+
+fn decode(token: &str) -> (String, Vec<serde_cbor::Value>) {
+    let split = token.find(':').expect("prefix") + 1;
+    let compressed = bs58::decode(&token[split..]).into_vec().expect("base58");
+    let mut decoder = zstd::stream::read::Decoder::with_dictionary(
+        &compressed[..],
+        gxt::Dictionary::builtin().as_bytes(),
+    )
+    .expect("decoder");
+    let mut raw = Vec::new();
+    std::io::Read::read_to_end(&mut decoder, &mut raw).expect("zstd");
+    let serde_cbor::Value::Array(values) = serde_cbor::from_slice(&raw).expect("cbor") else {
+        panic!("envelope is not an array");
+    };
+    (token[..split].to_string(), values)
+}
+
+/// Runs `f` repeatedly and returns the average duration of one run.
+fn measure(f: impl Fn() -> usize) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while start.elapsed() < MIN_DURATION {
+        std::hint::black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
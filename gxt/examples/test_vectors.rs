@@ -0,0 +1,1260 @@
+//! Generates the known-answer test vectors in `test-vectors/` at the root of the repository.
+//!
+//! Run with `cargo run -p gxt --example test_vectors --features test-vectors`.
+//!
+//! All keys, salts and nonces come from [`gxt::test_vectors::with_seed`], with the `seed`
+//! of the vector, so running the generator again creates the same ids and signatures.
+//! The compressed bytes of a token depend on the zstd implementation, so other
+//! implementations should compare the decoded result and not the token itself.
+//!
+//! Every vector describes one operation, its inputs and either the `expected` result
+//! or the name of the `error` variant. `gxt/tests/conformance.rs` checks them.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use curve25519_dalek::{EdwardsPoint, Scalar, constants::EIGHT_TORSION};
+use ed25519_dalek::{Signer, SigningKey};
+use gxt::test_vectors::{with_seed, with_version};
+use gxt::{
+    Dictionary, Encoding, GxtError, IdCard, Identity, JsonValue, KeyAgreement, ProtocolVersion,
+    RevocationReason, Token, Validity, json,
+};
+use serde::Serialize;
+use serde_cbor::Value as CborValue;
+use sha2::{Digest, Sha512};
+use vectors::{Operation, Vector, error_name, run};
+
+#[path = "../tests/vectors/mod.rs"]
+mod vectors;
+
+const CREATED_AT: u64 = 1_750_000_000;
+const EXPIRES_AT: u64 = CREATED_AT + 3600;
+const PASSWORD: &str = "correct horse battery staple";
+const SIGNATURE_DOMAIN: &[u8] = b"GXT";
+
+impl Vector {
+    fn new(name: &str, operation: Operation) -> Self {
+        Self {
+            name: name.into(),
+            seed: None,
+            operation,
+            token: None,
+            key: None,
+            password: None,
+            recovery_phrase: None,
+            data: None,
+            now: None,
+            parts: None,
+            revocations: None,
+            encoding: None,
+            payload: None,
+            recipients: None,
+            expected: None,
+            error: None,
+        }
+    }
+
+    fn seed(mut self, seed: &str) -> Self {
+        self.seed = Some(seed.into());
+        self
+    }
+
+    fn token(mut self, token: impl ToString) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    fn key(mut self, identity: &Identity) -> Self {
+        self.key = Some(identity.to_token().expect("key").to_string());
+        self
+    }
+
+    fn payload(mut self, payload: JsonValue) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    fn recipients(mut self, id_cards: &[&IdCard]) -> Self {
+        self.recipients = Some(id_cards.iter().map(ToString::to_string).collect());
+        self
+    }
+
+    /// Runs the operation and records its result.
+    fn expect_ok(mut self) -> Self {
+        match run(&self) {
+            Ok(expected) => self.expected = Some(expected),
+            Err(e) => panic!("{} failed: {e:?}", self.name),
+        }
+        self
+    }
+
+    /// Runs the operation and checks that it fails with the given error.
+    fn expect_error(mut self, error: &str) -> Self {
+        match run(&self) {
+            Ok(_) => panic!("{} succeeded", self.name),
+            Err(e) => assert_eq!(error_name(&e), error, "{}: {e:?}", self.name),
+        }
+        self.error = Some(error.into());
+        self
+    }
+}
+
+/// The contents of one file in `test-vectors/`.
+#[derive(Serialize)]
+struct VectorFile {
+    description: &'static str,
+    vectors: Vec<Vector>,
+}
+
+struct Identities {
+    alice: Identity,
+    bob: Identity,
+    carol: Identity,
+    mallory: Identity,
+}
+
+fn main() {
+    let ids = Identities {
+        alice: identity("alice"),
+        bob: identity("bob"),
+        carol: identity("carol"),
+        mallory: identity("mallory"),
+    };
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("workspace")
+        .join("test-vectors");
+    std::fs::create_dir_all(&dir).expect("create directory");
+    let files = [
+        (
+            "keys",
+            VectorFile {
+                description: "Derivation of the public keys from a private key, recovery phrases and password protected keys",
+                vectors: keys(&ids),
+            },
+        ),
+        (
+            "id-cards",
+            VectorFile {
+                description: "Verification of ID cards",
+                vectors: id_cards(&ids),
+            },
+        ),
+        (
+            "signing",
+            VectorFile {
+                description: "Verification of public messages, rotations, revocations, detached signatures and multi-part tokens",
+                vectors: signing(&ids),
+            },
+        ),
+        (
+            "encryption",
+            VectorFile {
+                description: "Decryption of messages with every key agreement and multiple recipients",
+                vectors: encryption(&ids),
+            },
+        ),
+        (
+            "legacy",
+            VectorFile {
+                description: "Verification and decryption of tokens created in older protocol versions",
+                vectors: legacy(&ids),
+            },
+        ),
+        (
+            "errors",
+            VectorFile {
+                description: "Tokens and inputs that must be rejected, with the expected error",
+                vectors: errors(&ids),
+            },
+        ),
+    ];
+    for (name, file) in files {
+        let path = dir.join(format!("{name}.json"));
+        let mut json = serde_json::to_string_pretty(&file).expect("json");
+        json.push('\n');
+        std::fs::write(&path, json).expect("write vectors");
+        println!("wrote {} vectors to {}", file.vectors.len(), path.display());
+    }
+}
+
+fn identity(name: &str) -> Identity {
+    with_seed(format!("identity/{name}").as_bytes(), Identity::generate)
+}
+
+/// Runs `f` with the deterministic random numbers of the vector.
+fn seeded<T>(seed: &str, f: impl FnOnce() -> Result<T, GxtError>) -> T {
+    with_seed(seed.as_bytes(), f).unwrap_or_else(|e| panic!("{seed}: {e:?}"))
+}
+
+fn keys(ids: &Identities) -> Vec<Vector> {
+    let mut vectors = Vec::new();
+    for (name, identity) in [
+        ("alice", &ids.alice),
+        ("bob", &ids.bob),
+        ("carol", &ids.carol),
+        ("mallory", &ids.mallory),
+    ] {
+        vectors.push(
+            Vector::new(&format!("derive {name}"), Operation::Derive)
+                .seed(&format!("identity/{name}"))
+                .key(identity)
+                .expect_ok(),
+        );
+    }
+    let mut recover = Vector::new("recover alice", Operation::Recover);
+    recover.recovery_phrase = Some(ids.alice.to_recovery_phrase());
+    vectors.push(recover.expect_ok());
+    let mut recover = Vector::new("recover alice in upper case", Operation::Recover);
+    recover.recovery_phrase = Some(ids.alice.to_recovery_phrase().to_uppercase());
+    vectors.push(recover.expect_ok());
+
+    let locked = seeded("password/alice", || {
+        ids.alice.to_token_with_password(PASSWORD)
+    });
+    let mut unlock = Vector::new("unlock alice", Operation::Unlock)
+        .seed("password/alice")
+        .token(locked);
+    unlock.password = Some(PASSWORD.into());
+    vectors.push(unlock.expect_ok());
+    vectors
+}
+
+fn id_cards(ids: &Identities) -> Vec<Vector> {
+    let cards = [
+        (
+            "id card with display name",
+            ids.alice
+                .make_id_card(json!({"display_name": "Alice"}))
+                .expect("id card")
+                .token()
+                .clone(),
+        ),
+        (
+            "id card without meta data",
+            ids.bob.make_id_card(()).expect("id card").token().clone(),
+        ),
+        (
+            "id card with nested meta data",
+            ids.carol
+                .make_id_card(json!({
+                    "display_name": "Cäröl 🗡",
+                    "data": {"guild": "Night Watch", "level": 42, "titles": ["Scout", "Archer"]},
+                }))
+                .expect("id card")
+                .token()
+                .clone(),
+        ),
+    ];
+    let mut vectors = Vec::new();
+    for (name, token) in cards {
+        for encoding in [Encoding::Base58, Encoding::Qr] {
+            let token = token.to_encoding(encoding).expect("encoding");
+            vectors.push(
+                Vector::new(&format!("{name} ({encoding})"), Operation::Verify)
+                    .token(token)
+                    .expect_ok(),
+            );
+        }
+    }
+    vectors
+}
+
+fn signing(ids: &Identities) -> Vec<Vector> {
+    let order = order_payload();
+    let mut vectors = Vec::new();
+    let public = ids
+        .alice
+        .message()
+        .payload(&order)
+        .sign()
+        .expect("public message");
+    for encoding in Encoding::all() {
+        vectors.push(
+            Vector::new(&format!("public message ({encoding})"), Operation::Verify)
+                .token(public.to_encoding(*encoding).expect("encoding"))
+                .expect_ok(),
+        );
+    }
+    let parent = gxt::verify_message::<JsonValue>(public.as_str())
+        .expect("public message")
+        .id;
+    let tokens = [
+        (
+            "public message without dictionary",
+            ids.alice.message().payload(&order).dictionary(None).sign(),
+        ),
+        (
+            "public message with null payload",
+            ids.alice.message().sign(),
+        ),
+        (
+            "public message with scalar payload",
+            ids.alice.message().payload(&-1_234_567_890_123_i64).sign(),
+        ),
+        (
+            "public message with parent",
+            ids.bob
+                .message()
+                .payload(&json!({"accepted": true}))
+                .parent(parent.clone())
+                .sign(),
+        ),
+        (
+            "public message with validity",
+            ids.bob
+                .message()
+                .payload(&json!({"accepted": true}))
+                .parent(parent)
+                .validity(validity())
+                .sign(),
+        ),
+        (
+            "rotation",
+            ids.alice
+                .rotate_to(&ids.carol)
+                .map(|rotation| rotation.to_string())
+                .and_then(|rotation| rotation.parse::<Token>()),
+        ),
+        (
+            "rotation with creation time",
+            ids.alice
+                .rotate_to_with_validity(
+                    &ids.carol,
+                    Validity {
+                        created_at: Some(CREATED_AT),
+                        expires_at: None,
+                    },
+                )
+                .map(|rotation| rotation.to_string())
+                .and_then(|rotation| rotation.parse::<Token>()),
+        ),
+        (
+            "revocation",
+            ids.mallory
+                .revoke(RevocationReason::Compromised)
+                .map(|revocation| revocation.to_string())
+                .and_then(|revocation| revocation.parse::<Token>()),
+        ),
+    ];
+    for (name, token) in tokens {
+        vectors.push(
+            Vector::new(name, Operation::Verify)
+                .token(token.expect(name))
+                .expect_ok(),
+        );
+    }
+
+    let mut at = Vector::new("public message before its expiry", Operation::VerifyAt).token(
+        ids.alice
+            .message()
+            .payload(&order)
+            .validity(validity())
+            .sign()
+            .expect("public message"),
+    );
+    at.now = Some(EXPIRES_AT - 1);
+    vectors.push(at.expect_ok());
+    let mut skewed = Vector::new(
+        "public message created within the allowed clock skew",
+        Operation::VerifyAt,
+    )
+    .token(
+        ids.alice
+            .message()
+            .payload(&order)
+            .validity(validity())
+            .sign()
+            .expect("public message"),
+    );
+    skewed.now = Some(CREATED_AT - gxt::ALLOWED_CLOCK_SKEW);
+    vectors.push(skewed.expect_ok());
+
+    let mut revoked_other = Vector::new(
+        "public message of a key that was not revoked",
+        Operation::VerifyWithRevocations,
+    )
+    .token(&public);
+    revoked_other.revocations = Some(vec![
+        ids.mallory
+            .revoke(RevocationReason::Compromised)
+            .expect("revocation")
+            .to_string(),
+    ]);
+    vectors.push(revoked_other.expect_ok());
+
+    for encoding in Encoding::all() {
+        let mut encode = Vector::new(
+            &format!("public message in {encoding} encoding"),
+            Operation::Encode,
+        )
+        .token(&public);
+        encode.encoding = Some(encoding.to_string());
+        vectors.push(encode.expect_ok());
+    }
+    vectors.push(
+        Vector::new("sign public message", Operation::Sign)
+            .key(&ids.alice)
+            .payload(order.clone())
+            .expect_ok(),
+    );
+
+    let data = b"The quick brown fox jumps over the lazy dog";
+    let mut detached = Vector::new("detached signature", Operation::VerifyDetached)
+        .token(ids.alice.sign_detached(data).expect("signature"));
+    detached.data = Some(hex::encode(data));
+    vectors.push(detached.expect_ok());
+
+    for (name, encoding) in [
+        ("parts", Encoding::Base58),
+        ("parts in qr encoding", Encoding::Qr),
+    ] {
+        let mut parts = public
+            .to_encoding(encoding)
+            .expect("encoding")
+            .split_into_parts(200)
+            .expect("parts");
+        parts.reverse();
+        let mut join = Vector::new(name, Operation::Join);
+        join.parts = Some(parts);
+        vectors.push(join.expect_ok());
+    }
+    vectors
+}
+
+fn encryption(ids: &Identities) -> Vec<Vector> {
+    let bob = ids.bob.make_id_card(()).expect("id card");
+    let carol = ids.carol.make_id_card(()).expect("id card");
+    let order = order_payload();
+    let mut vectors = Vec::new();
+    for (name, agreement) in [
+        ("static", KeyAgreement::Static),
+        ("ephemeral", KeyAgreement::Ephemeral),
+        ("ephemeral static", KeyAgreement::EphemeralStatic),
+    ] {
+        let seed = format!("encryption/{name}");
+        let single = seeded(&seed, || {
+            ids.alice
+                .message()
+                .payload(&order)
+                .to(&bob)
+                .key_agreement(agreement)
+                .encrypt()
+        });
+        vectors.push(
+            Vector::new(&format!("{name} key agreement"), Operation::Decrypt)
+                .seed(&seed)
+                .token(single)
+                .key(&ids.bob)
+                .expect_ok(),
+        );
+
+        let seed = format!("encryption/{name}/many");
+        let many = seeded(&seed, || {
+            ids.alice
+                .message()
+                .payload(&order)
+                .to_all([&bob, &carol])
+                .key_agreement(agreement)
+                .encrypt()
+        });
+        for (recipient, identity) in [("bob", &ids.bob), ("carol", &ids.carol)] {
+            vectors.push(
+                Vector::new(
+                    &format!("{name} key agreement for many, decrypted by {recipient}"),
+                    Operation::Decrypt,
+                )
+                .seed(&seed)
+                .token(&many)
+                .key(identity)
+                .expect_ok(),
+            );
+        }
+    }
+
+    let seeds_and_tokens = [
+        (
+            "encryption/without dictionary",
+            "message without dictionary",
+            seeded("encryption/without dictionary", || {
+                ids.alice
+                    .message()
+                    .payload(&order)
+                    .to(&bob)
+                    .dictionary(None)
+                    .encrypt()
+            }),
+        ),
+        (
+            "encryption/compressed plaintext",
+            "message with compressed plaintext",
+            seeded("encryption/compressed plaintext", || {
+                ids.alice
+                    .message()
+                    .payload(&order)
+                    .to(&bob)
+                    .compress_plaintext(true)
+                    .encrypt()
+            }),
+        ),
+        (
+            "encryption/bech32",
+            "message in bech32 encoding",
+            seeded("encryption/bech32", || {
+                ids.alice
+                    .message()
+                    .payload(&order)
+                    .to(&bob)
+                    .encoding(Encoding::Bech32)
+                    .encrypt()
+            }),
+        ),
+        (
+            "encryption/parent and validity",
+            "message with parent and validity",
+            seeded("encryption/parent and validity", || {
+                ids.alice
+                    .message()
+                    .payload(&json!({"accepted": false, "reason": "too expensive"}))
+                    .to(&bob)
+                    .parent(bob.id())
+                    .validity(validity())
+                    .encrypt()
+            }),
+        ),
+    ];
+    vectors.push(
+        Vector::new("encrypt for many", Operation::Encrypt)
+            .key(&ids.alice)
+            .payload(order.clone())
+            .recipients(&[&bob, &carol])
+            .expect_ok(),
+    );
+    for (seed, name, token) in seeds_and_tokens {
+        vectors.push(
+            Vector::new(name, Operation::Decrypt)
+                .seed(seed)
+                .token(token)
+                .key(&ids.bob)
+                .expect_ok(),
+        );
+    }
+    vectors
+}
+
+/// Tokens created by the encoders of older versions, which must keep working.
+///
+/// Version 4 stores keys, ids and signatures as hex text and does not sign the parent,
+/// so a message whose parent was replaced still verifies. From version 5 on, that breaks the id.
+fn legacy(ids: &Identities) -> Vec<Vector> {
+    let v4 = ProtocolVersion::get(4).expect("version 4");
+    let v5 = ProtocolVersion::get(5).expect("version 5");
+    let v6 = ProtocolVersion::get(6).expect("version 6");
+    let v7 = ProtocolVersion::get(7).expect("version 7");
+    let bob = ids.bob.make_id_card(()).expect("id card");
+    let order = order_payload();
+    let parent = gxt::verify_message::<JsonValue>(
+        ids.alice
+            .message()
+            .payload(&order)
+            .sign()
+            .expect("public message")
+            .as_str(),
+    )
+    .expect("public message")
+    .id;
+    let reply = |version| {
+        with_version(version, || {
+            ids.bob
+                .message()
+                .payload(&json!({"accepted": true}))
+                .parent(parent.clone())
+                .sign()
+                .expect("public message")
+        })
+    };
+    let mut vectors = vec![
+        Vector::new("version 4 id card", Operation::Verify)
+            .token(with_version(v4, || {
+                ids.alice
+                    .make_id_card(json!({"display_name": "Alice"}))
+                    .expect("id card")
+                    .token()
+                    .clone()
+            }))
+            .expect_ok(),
+        Vector::new("version 4 public message", Operation::Verify)
+            .token(with_version(v4, || {
+                ids.alice
+                    .message()
+                    .payload(&order)
+                    .sign()
+                    .expect("public message")
+            }))
+            .expect_ok(),
+    ];
+
+    for (version, name) in [(v4, "unauthenticated"), (v5, "authenticated")] {
+        let token = reply(version);
+        vectors.push(
+            Vector::new(
+                &format!("version {} message with {name} parent", version.version),
+                Operation::Verify,
+            )
+            .token(&token)
+            .expect_ok(),
+        );
+        let (prefix, mut values) = decode(token.as_str());
+        values[4] = CborValue::Text(hex::encode([0x42; 32]));
+        let replaced = Vector::new(
+            &format!("version {} message with replaced parent", version.version),
+            Operation::Verify,
+        )
+        .token(encode(&prefix, &CborValue::Array(values)));
+        vectors.push(if version.authenticates_parent {
+            replaced.expect_error("BadId")
+        } else {
+            replaced.expect_ok()
+        });
+    }
+
+    for (version, name, validity) in [
+        (v4, "version 4 encrypted message", Validity::default()),
+        (v6, "version 6 encrypted message with validity", validity()),
+        (v7, "version 7 encrypted message", validity()),
+    ] {
+        let seed = format!("legacy/{name}");
+        let token = seeded(&seed, || {
+            with_version(version, || {
+                ids.alice
+                    .message()
+                    .payload(&order)
+                    .to(&bob)
+                    .validity(validity)
+                    .encrypt()
+            })
+        });
+        vectors.push(
+            Vector::new(name, Operation::Decrypt)
+                .seed(&seed)
+                .token(token)
+                .key(&ids.bob)
+                .expect_ok(),
+        );
+    }
+    vectors.push(
+        Vector::new("upgrade version 4 id card", Operation::Upgrade)
+            .token(with_version(v4, || {
+                ids.alice
+                    .make_id_card(json!({"display_name": "Alice"}))
+                    .expect("id card")
+                    .token()
+                    .clone()
+            }))
+            .key(&ids.alice)
+            .expect_ok(),
+    );
+    let seed = "legacy/version 4 encrypted message for many";
+    let many = seeded(seed, || {
+        with_version(v4, || {
+            ids.alice
+                .message()
+                .payload(&order)
+                .to_all([&bob, &ids.carol.make_id_card(()).expect("id card")])
+                .encrypt()
+        })
+    });
+    vectors.push(
+        Vector::new("version 4 encrypted message for many", Operation::Decrypt)
+            .seed(seed)
+            .token(many)
+            .key(&ids.carol)
+            .expect_ok(),
+    );
+    vectors
+}
+
+#[allow(clippy::too_many_lines)]
+fn errors(ids: &Identities) -> Vec<Vector> {
+    let bob = ids.bob.make_id_card(()).expect("id card");
+    let order = order_payload();
+    let public = ids.alice.message().payload(&order).sign().expect("public");
+    let (prefix, values) = decode(public.as_str());
+    let mut vectors = Vec::new();
+    let mut verify = |name: &str, token: &str, error: &str| {
+        vectors.push(
+            Vector::new(name, Operation::Verify)
+                .token(token)
+                .expect_error(error),
+        );
+    };
+
+    // text encoding
+    let body = &public.as_str()[prefix.len()..];
+    verify(
+        "unknown prefix",
+        &format!("gxx:{body}"),
+        "UnknownPayloadKind",
+    );
+    verify("missing prefix", body, "BadPrefix");
+    verify(
+        "invalid base58 character",
+        &format!("{prefix}0{}", &body[1..]),
+        "Decode",
+    );
+    let base64 = public.to_encoding(Encoding::Base64Url).expect("encoding");
+    verify(
+        "invalid base64 character",
+        &format!("{}!", base64.as_str()),
+        "BadEncoding",
+    );
+    let bech32 = public.to_encoding(Encoding::Bech32).expect("encoding");
+    verify(
+        "bech32 typo",
+        &swap_last_chars(bech32.as_str(), 20),
+        "BadChecksum",
+    );
+    verify(
+        "token too long",
+        &format!("{prefix}{}", "1".repeat(88 * 1024 + 1 - prefix.len())),
+        "TokenTooLong",
+    );
+    verify(
+        "data longer than the compressed size limit",
+        &format!("{prefix}{}", "z".repeat(64 * 1024 * 1366 / 1000 + 2)),
+        "TokenTooLong",
+    );
+
+    verify(
+        "data larger than the compressed size limit",
+        &format!("{prefix}{}", "1".repeat(64 * 1024 + 1)),
+        "CompressedTooLarge",
+    );
+
+    // compression
+    verify(
+        "not compressed",
+        &format!(
+            "{prefix}{}",
+            bs58::encode(serde_cbor::to_vec(&CborValue::Array(values.clone())).expect("cbor"))
+                .into_string()
+        ),
+        "Compression",
+    );
+    let custom =
+        Dictionary::train(&vec![order.clone(); 100], 1024, 0x1234_5678).expect("dictionary");
+    let with_custom = zstd::bulk::Compressor::with_dictionary(3, custom.as_bytes())
+        .expect("compressor")
+        .compress(&serde_cbor::to_vec(&CborValue::Array(values.clone())).expect("cbor"))
+        .expect("compress");
+    verify(
+        "unknown dictionary",
+        &format!("{prefix}{}", bs58::encode(with_custom).into_string()),
+        "UnknownDictionary",
+    );
+    let bomb = zstd::encode_all(&vec![0u8; 2 * 1024 * 1024][..], 3).expect("compress");
+    verify(
+        "decompressed too large",
+        &format!("{prefix}{}", bs58::encode(bomb).into_string()),
+        "DecompressedTooLarge",
+    );
+
+    // envelope
+    let not_cbor = zstd::bulk::Compressor::with_dictionary(3, Dictionary::builtin().as_bytes())
+        .expect("compressor")
+        .compress(&[0xff])
+        .expect("compress");
+    verify(
+        "envelope is not cbor",
+        &format!("{prefix}{}", bs58::encode(not_cbor).into_string()),
+        "Cbor",
+    );
+    let v7 = ProtocolVersion::get(7).expect("version 7");
+    let hex_public = with_version(v7, || {
+        ids.alice
+            .message()
+            .payload(&order)
+            .sign()
+            .expect("public message")
+    });
+    let (hex_prefix, mut hex_values) = decode(hex_public.as_str());
+    hex_values[1] = CborValue::Text("zz".repeat(32));
+    verify(
+        "verification key is not hex",
+        &encode(&hex_prefix, &CborValue::Array(hex_values)),
+        "BadHex",
+    );
+    verify(
+        "envelope is not an array",
+        &encode(&prefix, &CborValue::Map(BTreeMap::new())),
+        "Invalid",
+    );
+    for version in [3, 9] {
+        let mut changed = values.clone();
+        changed[0] = CborValue::Integer(version);
+        verify(
+            &format!("unsupported version {version}"),
+            &encode(&prefix, &CborValue::Array(changed)),
+            "UnsupportedVersion",
+        );
+    }
+    let mut changed = values.clone();
+    changed.pop();
+    verify(
+        "missing field",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "Invalid",
+    );
+    let mut changed = values.clone();
+    changed[1] = CborValue::Bytes(vec![0; 31]);
+    verify(
+        "short verification key",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "InvalidHexSize",
+    );
+    let mut changed = values.clone();
+    changed[1] = CborValue::Text(ids.alice.verification_key());
+    verify(
+        "verification key as text",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "Invalid",
+    );
+    let mut changed = values.clone();
+    changed[5] = CborValue::Integer(-1);
+    verify(
+        "negative timestamp",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "Invalid",
+    );
+
+    // id and signature
+    let mut changed = values.clone();
+    changed[3] = serde_cbor::value::to_value(json!({"tampered": true})).expect("cbor");
+    verify(
+        "tampered payload",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "BadId",
+    );
+    let mut changed = values.clone();
+    changed[4] = CborValue::Bytes(vec![0x11; 32]);
+    verify(
+        "tampered parent",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "BadId",
+    );
+    let mut changed = values.clone();
+    let CborValue::Bytes(signature) = &mut changed[8] else {
+        panic!("signature");
+    };
+    signature[0] ^= 1;
+    verify(
+        "tampered signature",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "BadSig",
+    );
+    let mut changed = values.clone();
+    changed[1] = CborValue::Bytes(hex::decode(ids.mallory.verification_key()).expect("hex"));
+    changed[7] = CborValue::Bytes(blake3::hash(&canonical(&changed)).as_bytes().to_vec());
+    verify(
+        "signed by a different key",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "BadSig",
+    );
+    // The identity point as key and R with s = 0 solves the verification equation
+    // for every message, which only strict verification rejects.
+    let mut identity_point = vec![0; 32];
+    identity_point[0] = 1;
+    let mut changed = values.clone();
+    changed[1] = CborValue::Bytes(identity_point.clone());
+    changed[7] = CborValue::Bytes(blake3::hash(&canonical(&changed)).as_bytes().to_vec());
+    changed[8] = CborValue::Bytes([identity_point, vec![0; 32]].concat());
+    verify(
+        "weak verification key",
+        &encode(&prefix, &CborValue::Array(changed)),
+        "BadSig",
+    );
+    verify(
+        "signature with a small order component in R",
+        &encode(
+            &prefix,
+            &CborValue::Array(sign_with_small_order_r(&ids.alice, values.clone())),
+        ),
+        "BadSig",
+    );
+
+    // expiry
+    let expiring = ids
+        .alice
+        .message()
+        .payload(&order)
+        .validity(validity())
+        .sign()
+        .expect("public message");
+    let mut expired = Vector::new("expired", Operation::VerifyAt).token(&expiring);
+    expired.now = Some(EXPIRES_AT);
+    vectors.push(expired.expect_error("Expired"));
+    let mut future = Vector::new("created in the future", Operation::VerifyAt).token(&expiring);
+    future.now = Some(CREATED_AT - gxt::ALLOWED_CLOCK_SKEW - 1);
+    vectors.push(future.expect_error("NotYetValid"));
+
+    // decryption
+    let message = seeded("errors/message", || {
+        ids.alice
+            .message()
+            .payload(&order)
+            .to(&bob)
+            .key_agreement(KeyAgreement::Ephemeral)
+            .encrypt()
+    });
+    vectors.push(
+        Vector::new("not a recipient", Operation::Decrypt)
+            .seed("errors/message")
+            .token(&message)
+            .key(&ids.carol)
+            .expect_error("AccessDenied"),
+    );
+    vectors.push(
+        Vector::new("decrypt public message", Operation::Decrypt)
+            .token(&public)
+            .key(&ids.bob)
+            .expect_error("Invalid"),
+    );
+    let (message_prefix, message_values) = decode(message.as_str());
+    let moved = resign(&ids.mallory, message_values.clone());
+    vectors.push(
+        Vector::new(
+            "ciphertext moved into a different envelope",
+            Operation::Decrypt,
+        )
+        .seed("errors/message")
+        .token(encode(&message_prefix, &CborValue::Array(moved)))
+        .key(&ids.bob)
+        .expect_error("Encryption"),
+    );
+    for (name, field, value, error) in [
+        ("tampered ciphertext", "ct", None, "Encryption"),
+        (
+            "unknown key agreement",
+            "kdf",
+            Some(CborValue::Text("x448".into())),
+            "Invalid",
+        ),
+        (
+            "unknown compression",
+            "cmp",
+            Some(CborValue::Text("brotli".into())),
+            "Invalid",
+        ),
+        (
+            "short nonce",
+            "n24",
+            Some(CborValue::Bytes(vec![0; 12])),
+            "InvalidHexSize",
+        ),
+    ] {
+        let mut payload = message_values[3].clone();
+        let enc = enc_map(&mut payload);
+        let key = CborValue::Text(field.into());
+        match value {
+            Some(value) => {
+                enc.insert(key, value);
+            }
+            None => {
+                let Some(CborValue::Bytes(bytes)) = enc.get_mut(&key) else {
+                    panic!("{field}");
+                };
+                bytes[0] ^= 1;
+            }
+        }
+        let changed = resign(&ids.alice, values_with_payload(&message_values, payload));
+        vectors.push(
+            Vector::new(name, Operation::Decrypt)
+                .seed("errors/message")
+                .token(encode(&message_prefix, &CborValue::Array(changed)))
+                .key(&ids.bob)
+                .expect_error(error),
+        );
+    }
+
+    // keys
+    let mut derive_id_card = Vector::new("derive from id card", Operation::Derive);
+    derive_id_card.key = Some(bob.to_string());
+    vectors.push(derive_id_card.expect_error("UnexpectedKind"));
+    let locked = seeded("password/alice", || {
+        ids.alice.to_token_with_password(PASSWORD)
+    });
+    let mut derive_locked = Vector::new("derive from locked key", Operation::Derive);
+    derive_locked.seed = Some("password/alice".into());
+    derive_locked.key = Some(locked.to_string());
+    vectors.push(derive_locked.expect_error("PasswordRequired"));
+    let mut unlock = Vector::new("wrong password", Operation::Unlock)
+        .seed("password/alice")
+        .token(&locked);
+    unlock.password = Some("Tr0ub4dor&3".into());
+    vectors.push(unlock.expect_error("BadPassword"));
+    let (locked_prefix, locked_values) = decode(locked.as_str());
+    for (name, field, value) in [
+        ("memory cost above the limit", "m", 256 * 1024 + 1),
+        ("time cost above the limit", "t", 9),
+    ] {
+        let CborValue::Map(mut payload) = locked_values[3].clone() else {
+            panic!("locked key payload");
+        };
+        payload.insert(CborValue::Text(field.into()), CborValue::Integer(value));
+        let changed = resign(
+            &ids.alice,
+            values_with_payload(&locked_values, CborValue::Map(payload)),
+        );
+        let mut unlock = Vector::new(name, Operation::Unlock)
+            .seed("password/alice")
+            .token(encode(&locked_prefix, &CborValue::Array(changed)));
+        unlock.password = Some(PASSWORD.into());
+        vectors.push(unlock.expect_error("PasswordCostTooHigh"));
+    }
+    let phrase = ids.alice.to_recovery_phrase();
+    let mut words = phrase.split(' ').collect::<Vec<_>>();
+    words.swap(0, 1);
+    let mut recover = Vector::new("recovery phrase with swapped words", Operation::Recover);
+    recover.recovery_phrase = Some(words.join(" "));
+    vectors.push(recover.expect_error("BadRecoveryPhrase"));
+    let mut recover = Vector::new("recovery phrase with 12 words", Operation::Recover);
+    recover.recovery_phrase =
+        Some("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".into());
+    vectors.push(recover.expect_error("BadRecoveryPhrase"));
+
+    // detached signatures
+    let data = b"The quick brown fox jumps over the lazy dog";
+    let mut detached = Vector::new(
+        "detached signature over different data",
+        Operation::VerifyDetached,
+    )
+    .token(ids.alice.sign_detached(data).expect("signature"));
+    detached.data = Some(hex::encode(b"The quick brown fox jumps over the lazy cat"));
+    vectors.push(detached.expect_error("HashMismatch"));
+    vectors.push(
+        Vector::new(
+            "public message as detached signature",
+            Operation::VerifyDetached,
+        )
+        .token(&public)
+        .expect_error("UnexpectedKind"),
+    );
+
+    // parts
+    let parts = public.split_into_parts(200).expect("parts");
+    let mut join = Vector::new("missing part", Operation::Join);
+    join.parts = Some(parts[1..].to_vec());
+    vectors.push(join.expect_error("MissingParts"));
+    let other = ids
+        .bob
+        .message()
+        .payload(&order)
+        .sign()
+        .expect("public message")
+        .split_into_parts(200)
+        .expect("parts");
+    let mut join = Vector::new("part of a different token", Operation::Join);
+    join.parts = Some(vec![parts[0].clone(), other[1].clone()]);
+    vectors.push(join.expect_error("PartMismatch"));
+    let mut corrupted = parts.clone();
+    corrupted[0] = swap_last_chars(&corrupted[0], 20);
+    let mut join = Vector::new("corrupted part", Operation::Join);
+    join.parts = Some(corrupted);
+    vectors.push(join.expect_error("BadChecksum"));
+    let mut join = Vector::new("part with a huge total", Operation::Join);
+    join.parts = Some(vec![
+        "gxm1/18446744073709551615:00000000.abc.8c4ec46d".into(),
+    ]);
+    vectors.push(join.expect_error("TooManyParts"));
+
+    // revocations
+    let mut revoked = Vector::new(
+        "public message of a revoked key",
+        Operation::VerifyWithRevocations,
+    )
+    .token(
+        ids.mallory
+            .message()
+            .payload(&order)
+            .sign()
+            .expect("public message"),
+    );
+    revoked.revocations = Some(vec![
+        ids.mallory
+            .revoke(RevocationReason::Compromised)
+            .expect("revocation")
+            .to_string(),
+    ]);
+    vectors.push(revoked.expect_error("Revoked"));
+
+    // operations
+    let mut encode = Vector::new("unknown encoding", Operation::Encode).token(&public);
+    encode.encoding = Some("base85".into());
+    vectors.push(encode.expect_error("UnknownEncoding"));
+    vectors.push(
+        Vector::new("upgrade id card of a different key", Operation::Upgrade)
+            .token(&bob)
+            .key(&ids.alice)
+            .expect_error("WrongKey"),
+    );
+    vectors.push(
+        Vector::new("public message with recipients", Operation::Sign)
+            .key(&ids.alice)
+            .payload(order.clone())
+            .recipients(&[&bob])
+            .expect_error("UnexpectedRecipients"),
+    );
+    vectors.push(
+        Vector::new("encrypted message without recipients", Operation::Encrypt)
+            .key(&ids.alice)
+            .payload(order.clone())
+            .recipients(&[])
+            .expect_error("NoRecipients"),
+    );
+    vectors
+}
+
+fn order_payload() -> JsonValue {
+    json!({
+        "requests": [{
+            "id": "req-1",
+            "wanted": [{"id": "gold", "amount": 250}],
+            "offered": [{
+                "id": "iron_sword",
+                "display_name": "Iron Sword",
+                "attributes": [{"id": "strength", "amount": 5, "kind": "Flat"}],
+                "amount": 1,
+            }],
+        }],
+        "all_or_nothing": true,
+        "note": "Meet me in the tavern",
+    })
+}
+
+fn validity() -> Validity {
+    Validity {
+        created_at: Some(CREATED_AT),
+        expires_at: Some(EXPIRES_AT),
+    }
+}
+
+/// Swaps two neighbouring characters `offset` characters before the end.
+fn swap_last_chars(token: &str, offset: usize) -> String {
+    let mut chars = token.chars().collect::<Vec<_>>();
+    let i = chars.len() - offset;
+    let j = (i..chars.len())
+        .find(|&j| chars[j] != chars[i])
+        .expect("two different characters");
+    chars.swap(i, j);
+    chars.into_iter().collect()
+}
+
+/// Splits a Base58 token into its prefix and the fields of its envelope.
+fn decode(token: &str) -> (String, Vec<CborValue>) {
+    let split = token.find(':').expect("prefix") + 1;
+    let compressed = bs58::decode(&token[split..]).into_vec().expect("base58");
+    let mut raw = Vec::new();
+    zstd::stream::read::Decoder::with_dictionary(&compressed[..], Dictionary::builtin().as_bytes())
+        .expect("decoder")
+        .read_to_end(&mut raw)
+        .expect("zstd");
+    let CborValue::Array(values) = serde_cbor::from_slice(&raw).expect("cbor") else {
+        panic!("envelope is not an array");
+    };
+    (token[..split].to_string(), values)
+}
+
+/// Writes the envelope as Base58 token with the given prefix.
+fn encode(prefix: &str, envelope: &CborValue) -> String {
+    let raw = serde_cbor::to_vec(envelope).expect("cbor");
+    let compressed = zstd::bulk::Compressor::with_dictionary(3, Dictionary::builtin().as_bytes())
+        .expect("compressor")
+        .compress(&raw)
+        .expect("compress");
+    format!("{prefix}{}", bs58::encode(compressed).into_string())
+}
+
+/// The envelope without id and signature, which is hashed and signed.
+///
+/// The payloads of these vectors only contain maps with text keys, for which serde_cbor
+/// already uses the deterministic encoding of the specification.
+fn canonical(values: &[CborValue]) -> Vec<u8> {
+    let mut values = values.to_vec();
+    let len = values.len();
+    values[len - 2] = CborValue::Bytes(Vec::new());
+    values[len - 1] = CborValue::Bytes(Vec::new());
+    serde_cbor::to_vec(&CborValue::Array(values)).expect("cbor")
+}
+
+/// Replaces the keys of the envelope with the ones of the identity and signs it again.
+fn signing_key(identity: &Identity) -> SigningKey {
+    let seed: [u8; 32] = bip39::Mnemonic::parse(identity.to_recovery_phrase())
+        .expect("phrase")
+        .to_entropy()
+        .try_into()
+        .expect("32 bytes");
+    SigningKey::from_bytes(&seed)
+}
+
+fn resign(identity: &Identity, mut values: Vec<CborValue>) -> Vec<CborValue> {
+    let signing_key = signing_key(identity);
+    values[1] = CborValue::Bytes(hex::decode(identity.verification_key()).expect("hex"));
+    values[2] = CborValue::Bytes(hex::decode(identity.encryption_key()).expect("hex"));
+    let canonical = canonical(&values);
+    let mut preimage = SIGNATURE_DOMAIN.to_vec();
+    preimage.extend_from_slice(&canonical);
+    let len = values.len();
+    values[len - 2] = CborValue::Bytes(blake3::hash(&canonical).as_bytes().to_vec());
+    values[len - 1] = CborValue::Bytes(signing_key.sign(&preimage).to_bytes().to_vec());
+    values
+}
+
+/// Signs the values with a point of order two added to R, and `s` computed as if it was not there.
+///
+/// Strict verification rejects the signature, but the cofactorless batch equation only has the point
+/// multiplied by a random factor, which is zero for every even factor.
+fn sign_with_small_order_r(identity: &Identity, mut values: Vec<CborValue>) -> Vec<CborValue> {
+    let signing_key = signing_key(identity);
+    let canonical = canonical(&values);
+    let mut preimage = SIGNATURE_DOMAIN.to_vec();
+    preimage.extend_from_slice(&canonical);
+    let nonce = Scalar::from_bytes_mod_order_wide(
+        &Sha512::new()
+            .chain_update(b"small order R")
+            .chain_update(&preimage)
+            .finalize()
+            .into(),
+    );
+    let r = (EdwardsPoint::mul_base(&nonce) + EIGHT_TORSION[4]).compress();
+    let k = Scalar::from_bytes_mod_order_wide(
+        &Sha512::new()
+            .chain_update(r.as_bytes())
+            .chain_update(signing_key.verifying_key().as_bytes())
+            .chain_update(&preimage)
+            .finalize()
+            .into(),
+    );
+    let s = nonce + k * signing_key.to_scalar();
+    let len = values.len();
+    values[len - 2] = CborValue::Bytes(blake3::hash(&canonical).as_bytes().to_vec());
+    values[len - 1] = CborValue::Bytes([r.to_bytes(), s.to_bytes()].concat());
+    values
+}
+
+fn values_with_payload(values: &[CborValue], payload: CborValue) -> Vec<CborValue> {
+    let mut values = values.to_vec();
+    values[3] = payload;
+    values
+}
+
+fn enc_map(payload: &mut CborValue) -> &mut BTreeMap<CborValue, CborValue> {
+    let CborValue::Map(map) = payload else {
+        panic!("payload is not a map");
+    };
+    let Some(CborValue::Map(enc)) = map.get_mut(&CborValue::Text("enc".into())) else {
+        panic!("no enc field");
+    };
+    enc
+}
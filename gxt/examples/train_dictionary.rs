@@ -0,0 +1,238 @@
+//! Trains a built-in compression dictionary on typical advisory payloads and tokens.
+//!
+//! Run with `cargo run --release -p gxt --example train_dictionary -- <version>`,
+//! which writes `gxt/dictionaries/gxt-<version>.zdict` with the id `0x4758_0000 + version`.
+//!
+//! The samples are random, so every run creates a different dictionary.
+//! A released dictionary must never be replaced, train a new version instead
+//! and add it to the end of the built-in dictionaries.
+
+use gxt::advisory::{
+    AttributeModifier, IdCard, Item, ModifierKind, TradeOrder, TradeRequest, TradeResponse,
+};
+use gxt::{Dictionary, Identity, Token};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use serde_cbor::Value as CborValue;
+
+/// Size of the dictionary. Larger dictionaries barely help tokens of a few hundred bytes.
+const MAX_SIZE: usize = 4 * 1024;
+const PAYLOADS: usize = 2000;
+const TOKENS: usize = 200;
+/// Placeholder for training, because the built-in ids are reserved.
+const TRAINING_ID: u32 = 0x7fff_ffff;
+
+const ITEMS: [&str; 16] = [
+    "gold",
+    "silver",
+    "gem",
+    "health_potion",
+    "mana_potion",
+    "iron_sword",
+    "steel_sword",
+    "wooden_shield",
+    "leather_armor",
+    "iron_helmet",
+    "bow",
+    "arrow",
+    "ring_of_power",
+    "amulet",
+    "herb",
+    "iron_ore",
+];
+const ATTRIBUTES: [&str; 8] = [
+    "strength",
+    "agility",
+    "intellect",
+    "stamina",
+    "armor",
+    "crit_chance",
+    "attack_speed",
+    "fire_resistance",
+];
+const NAMES: [&str; 8] = [
+    "Alice", "Bob", "Carol", "Dave", "Eve", "Mallory", "Trent", "Peggy",
+];
+const NOTES: [&str; 4] = [
+    "Meet me in the tavern",
+    "Only today",
+    "Price is negotiable",
+    "Thanks for the trade",
+];
+
+/// Serializes like the payload it contains.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Sample {
+    Order(TradeOrder),
+    Response(TradeResponse),
+    IdCard(IdCard),
+    Envelope(CborValue),
+}
+
+fn main() {
+    let version = std::env::args()
+        .nth(1)
+        .and_then(|version| version.parse::<u16>().ok())
+        .expect("usage: train_dictionary <version>");
+    let mut rng = rand::thread_rng();
+
+    let mut samples = Vec::new();
+    for _ in 0..PAYLOADS {
+        samples.push(match rng.gen_range(0..4) {
+            0 => Sample::IdCard(id_card(&mut rng)),
+            1 => Sample::Response(TradeResponse {
+                order: trade_order(&mut rng),
+                trades: vec![trade_request(&mut rng, 1)],
+                note: note(&mut rng),
+            }),
+            _ => Sample::Order(trade_order(&mut rng)),
+        });
+    }
+
+    // Tokens are compressed without a dictionary, so their envelopes can be read back.
+    gxt::set_compression_dictionary(None).expect("no dictionary");
+    let alice = Identity::generate();
+    let bob = Identity::generate();
+    let bobs_id_card = bob.make_id_card(id_card(&mut rng)).expect("id card");
+    for _ in 0..TOKENS {
+        let order = trade_order(&mut rng);
+        let tokens = [
+            alice
+                .make_id_card(id_card(&mut rng))
+                .expect("id card")
+                .token()
+                .clone(),
+            alice
+                .make_public_message(&order, None)
+                .expect("public message"),
+            alice
+                .encrypt_message(&bobs_id_card, &order, None)
+                .expect("encrypted message"),
+            alice
+                .encrypt_message_for_many(
+                    &[
+                        bobs_id_card.clone(),
+                        alice.make_id_card(()).expect("id card"),
+                    ],
+                    &order,
+                    None,
+                )
+                .expect("encrypted message"),
+        ];
+        samples.extend(tokens.iter().map(|token| Sample::Envelope(envelope(token))));
+    }
+
+    let dictionary = Dictionary::train(&samples, MAX_SIZE, TRAINING_ID).expect("training");
+    let mut data = dictionary.as_bytes().to_vec();
+    // The id follows the four byte magic number.
+    data[4..8].copy_from_slice(&(0x4758_0000 + u32::from(version)).to_le_bytes());
+
+    let path = format!(
+        "{}/dictionaries/gxt-{version}.zdict",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    std::fs::write(&path, data).expect("write dictionary");
+    println!("wrote {} bytes to {path}", dictionary.as_bytes().len());
+}
+
+fn envelope(token: &Token) -> CborValue {
+    let text = token.as_str();
+    let body = &text[text.find(':').expect("prefix") + 1..];
+    let compressed = bs58::decode(body).into_vec().expect("base58");
+    let raw = zstd::decode_all(&compressed[..]).expect("zstd");
+    serde_cbor::from_slice(&raw).expect("cbor")
+}
+
+fn id_card(rng: &mut impl Rng) -> IdCard {
+    IdCard {
+        display_name: pick(rng, &NAMES).into(),
+        data: None,
+    }
+}
+
+fn trade_order(rng: &mut impl Rng) -> TradeOrder {
+    let count = rng.gen_range(1..=3);
+    TradeOrder {
+        requests: (1..=count).map(|i| trade_request(rng, i)).collect(),
+        all_or_nothing: rng.gen_bool(0.5),
+        note: note(rng),
+    }
+}
+
+fn trade_request(rng: &mut impl Rng, index: usize) -> TradeRequest {
+    TradeRequest {
+        id: format!("req-{index}"),
+        wanted: items(rng),
+        offered: items(rng),
+        data: None,
+    }
+}
+
+fn items(rng: &mut impl Rng) -> Vec<Item> {
+    let count = rng.gen_range(1..=2);
+    (0..count).map(|_| item(rng)).collect()
+}
+
+fn item(rng: &mut impl Rng) -> Item {
+    let id = pick(rng, &ITEMS);
+    let attributes = if id.contains('_') && rng.gen_bool(0.5) {
+        let count = rng.gen_range(1..=2);
+        (0..count).map(|_| attribute(rng)).collect()
+    } else {
+        Vec::new()
+    };
+    Item {
+        id: id.into(),
+        display_name: rng.gen_bool(0.7).then(|| display_name(id)),
+        description: rng
+            .gen_bool(0.2)
+            .then(|| format!("A {} of fine quality.", display_name(id).to_lowercase())),
+        attributes,
+        amount: if rng.gen_bool(0.5) {
+            1
+        } else {
+            rng.gen_range(2..5000)
+        },
+        data: None,
+    }
+}
+
+fn attribute(rng: &mut impl Rng) -> AttributeModifier {
+    let id = pick(rng, &ATTRIBUTES);
+    let percent = rng.gen_bool(0.3);
+    AttributeModifier {
+        id: id.into(),
+        display_name: rng.gen_bool(0.7).then(|| display_name(id)),
+        amount: rng.gen_range(1..50),
+        kind: if percent {
+            ModifierKind::Percent
+        } else {
+            ModifierKind::Flat
+        },
+        data: None,
+    }
+}
+
+fn note(rng: &mut impl Rng) -> Option<String> {
+    rng.gen_bool(0.3).then(|| pick(rng, &NOTES).into())
+}
+
+fn pick<'a>(rng: &mut impl Rng, values: &[&'a str]) -> &'a str {
+    values.choose(rng).expect("values are not empty")
+}
+
+/// Turns `health_potion` into `Health Potion`.
+fn display_name(id: &str) -> String {
+    id.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
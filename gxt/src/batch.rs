@@ -0,0 +1,56 @@
+use std::num::NonZeroUsize;
+use std::thread;
+
+use serde::de::DeserializeOwned;
+
+use crate::{Envelope, GxtError, Limits, verify_message_with_limits};
+
+/// Number of tokens below which verifying them on another thread is not worth starting it.
+const MIN_CHUNK_LEN: usize = 32;
+
+/// Verifies many tokens at once and returns the result of every token, in the same order.
+///
+/// The tokens are decoded and verified in parallel, on as many threads as there are CPUs,
+/// and every result is the same as the one of [`crate::verify_message`].
+///
+/// Ed25519 batch verification is deliberately not used: it can accept signatures with small order
+/// components that strict verification rejects, and ruling those out costs as much as verifying
+/// the signature on its own.
+pub fn verify_many<P: DeserializeOwned + Send, S: AsRef<str> + Sync>(
+    tokens: &[S],
+) -> Vec<Result<Envelope<P>, GxtError>> {
+    verify_many_with_limits(tokens, &Limits::default())
+}
+
+/// Verifies many tokens at once like [`verify_many`],
+/// using custom size limits instead of the default ones.
+pub fn verify_many_with_limits<P: DeserializeOwned + Send, S: AsRef<str> + Sync>(
+    tokens: &[S],
+    limits: &Limits,
+) -> Vec<Result<Envelope<P>, GxtError>> {
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let chunk_len = tokens.len().div_ceil(threads).max(MIN_CHUNK_LEN);
+    if tokens.len() <= chunk_len {
+        return verify_chunk(tokens, limits);
+    }
+    thread::scope(|scope| {
+        let handles = tokens
+            .chunks(chunk_len)
+            .map(|chunk| scope.spawn(move || verify_chunk(chunk, limits)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("verification does not panic"))
+            .collect()
+    })
+}
+
+fn verify_chunk<P: DeserializeOwned, S: AsRef<str>>(
+    tokens: &[S],
+    limits: &Limits,
+) -> Vec<Result<Envelope<P>, GxtError>> {
+    tokens
+        .iter()
+        .map(|token| verify_message_with_limits(token.as_ref(), limits))
+        .collect()
+}
@@ -0,0 +1,204 @@
+use serde::Serialize;
+
+use crate::{
+    Bytes32, Dictionary, Encoding, GxtError, IdCard, Identity, KeyAgreement, PayloadKind, Token,
+    TokenOptions, Validity, encrypt_payload, encrypt_payload_for_many, make_with_options,
+    parse_hex,
+};
+
+/// Composes a message step by step and creates its token.
+///
+/// Start with [`Identity::message`], set what is needed and finish with
+/// [`MessageBuilder::sign`] for a public message or [`MessageBuilder::encrypt`] for an encrypted one.
+/// Everything that is not set uses the same defaults as the other functions of this crate.
+pub struct MessageBuilder<'a, P: Serialize + ?Sized = ()> {
+    identity: &'a Identity,
+    recipients: Vec<Bytes32>,
+    payload: &'a P,
+    parent: Option<String>,
+    validity: Validity,
+    agreement: KeyAgreement,
+    options: TokenOptions,
+}
+
+impl<'a> MessageBuilder<'a> {
+    /// Starts a message from the identity, with `null` as payload.
+    pub fn new(identity: &'a Identity) -> Self {
+        Self {
+            identity,
+            recipients: Vec::new(),
+            payload: &(),
+            parent: None,
+            validity: Validity::default(),
+            agreement: KeyAgreement::default(),
+            options: TokenOptions::default(),
+        }
+    }
+}
+
+impl<'a, P: Serialize + ?Sized> MessageBuilder<'a, P> {
+    /// Sets the payload.
+    pub fn payload<Q: Serialize + ?Sized>(self, payload: &'a Q) -> MessageBuilder<'a, Q> {
+        MessageBuilder {
+            identity: self.identity,
+            recipients: self.recipients,
+            payload,
+            parent: self.parent,
+            validity: self.validity,
+            agreement: self.agreement,
+            options: self.options,
+        }
+    }
+
+    /// Adds the owner of the ID card as recipient. Adding the same recipient again does nothing.
+    pub fn to(mut self, id_card: &IdCard) -> Self {
+        if !self.recipients.contains(&id_card.encryption_key) {
+            self.recipients.push(id_card.encryption_key);
+        }
+        self
+    }
+
+    /// Adds the owners of all ID cards as recipients.
+    pub fn to_all<'c>(self, id_cards: impl IntoIterator<Item = &'c IdCard>) -> Self {
+        id_cards
+            .into_iter()
+            .fold(self, |builder, id_card| builder.to(id_card))
+    }
+
+    /// Sets the id of the message this message answers.
+    pub fn parent(mut self, parent: impl Into<String>) -> Self {
+        self.parent = Some(parent.into());
+        self
+    }
+
+    pub(crate) fn maybe_parent(mut self, parent: Option<String>) -> Self {
+        self.parent = parent;
+        self
+    }
+
+    /// Sets the time window in which the message is valid.
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Sets how the key of an encrypted message is agreed on.
+    pub fn key_agreement(mut self, agreement: KeyAgreement) -> Self {
+        self.agreement = agreement;
+        self
+    }
+
+    /// Sets how the token is written as text. The default is [`Encoding::Base58`].
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.options.encoding = encoding;
+        self
+    }
+
+    /// Sets the dictionary the token is compressed with, or `None` to compress it without one.
+    ///
+    /// The default is the dictionary selected with [`crate::set_compression_dictionary`].
+    /// Recipients must know a custom dictionary to decode the token.
+    pub fn dictionary(mut self, dictionary: Option<Dictionary>) -> Self {
+        self.options.dictionary = dictionary;
+        self
+    }
+
+    /// Compresses the payload of an encrypted message with the dictionary before encrypting it.
+    ///
+    /// This makes encrypted messages shorter, but their length then tells how similar the payload is
+    /// to the dictionary. If an attacker can choose parts of a payload that also contains secrets,
+    /// they can guess the secrets from the length of the tokens. The default is `false`.
+    /// Public messages are always compressed with the dictionary.
+    pub fn compress_plaintext(mut self, compress: bool) -> Self {
+        self.options.compress_plaintext = compress;
+        self
+    }
+
+    /// Creates a **public** message, which is signed but not encrypted.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::UnexpectedRecipients`] if recipients were added.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn sign(&self) -> Result<Token, GxtError> {
+        if !self.recipients.is_empty() {
+            return Err(GxtError::UnexpectedRecipients);
+        }
+        make_with_options(
+            &self.identity.signing_key,
+            PayloadKind::Public,
+            serde_cbor::value::to_value(self.payload)?,
+            self.parsed_parent()?,
+            self.validity,
+            &self.options,
+        )?
+        .parse()
+    }
+
+    /// Creates an **encrypted** message for all recipients.
+    ///
+    /// A message for more than one recipient encrypts the payload only once with a random key,
+    /// which is then encrypted for every recipient.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::NoRecipients`] if no recipient was added.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt(&self) -> Result<Token, GxtError> {
+        match self.recipients.as_slice() {
+            [] => Err(GxtError::NoRecipients),
+            [recipient] => encrypt_payload(
+                self.identity,
+                recipient,
+                self.payload,
+                self.parsed_parent()?,
+                self.validity,
+                self.agreement,
+                &self.options,
+            )?
+            .parse(),
+            recipients => encrypt_payload_for_many(
+                self.identity,
+                recipients,
+                self.payload,
+                self.parsed_parent()?,
+                self.validity,
+                self.agreement,
+                &self.options,
+            )?
+            .parse(),
+        }
+    }
+
+    fn parsed_parent(&self) -> Result<Option<Bytes32>, GxtError> {
+        self.parent.as_deref().map(parse_hex::<32>).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_cbor::Value as CborValue;
+
+    use super::*;
+
+    fn is_compressed(token: &Token) -> bool {
+        let envelope = token.verify::<CborValue>().unwrap();
+        let CborValue::Map(payload) = envelope.payload else {
+            panic!("payload is not a map");
+        };
+        let Some(CborValue::Map(enc)) = payload.get(&CborValue::Text("enc".into())) else {
+            panic!("no enc");
+        };
+        enc.contains_key(&CborValue::Text("cmp".into()))
+    }
+
+    #[test]
+    fn encrypted_payloads_are_only_compressed_on_request() {
+        let alice = Identity::generate();
+        let bob = Identity::generate().make_id_card(()).unwrap();
+        let payload = "a payload that compresses well ".repeat(4);
+        let message = alice.message().payload(&payload).to(&bob);
+        assert!(!is_compressed(&message.encrypt().unwrap()));
+        assert!(is_compressed(
+            &message.compress_plaintext(true).encrypt().unwrap()
+        ));
+    }
+}
@@ -0,0 +1,255 @@
+//! Deterministic CBOR encoding as defined in RFC 8949, section 4.2.1.
+//!
+//! Everything that is hashed or signed is encoded with these rules, so other implementations
+//! can reproduce ids and signatures byte for byte:
+//! - integers, lengths and tags use the shortest possible head
+//! - arrays, maps, byte and text strings always have a definite length
+//! - map entries are sorted by the bytewise lexicographic order of their encoded keys
+//! - floats use the shortest of half, single and double precision that keeps their value,
+//!   NaN is always encoded as the half precision quiet NaN `0xf97e00`
+
+use serde_cbor::Value as CborValue;
+
+use crate::GxtError;
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+/// Encodes the value with the deterministic encoding rules.
+///
+/// # Errors
+/// - returns [`GxtError::Invalid`] if an integer does not fit into 64 bits,
+///   or a map contains two keys with the same encoding.
+pub(crate) fn to_vec(value: &CborValue) -> Result<Vec<u8>, GxtError> {
+    let mut out = Vec::new();
+    write_value(&mut out, value)?;
+    Ok(out)
+}
+
+fn write_value(out: &mut Vec<u8>, value: &CborValue) -> Result<(), GxtError> {
+    match value {
+        CborValue::Null => out.push(0xf6),
+        CborValue::Bool(false) => out.push(0xf4),
+        CborValue::Bool(true) => out.push(0xf5),
+        CborValue::Integer(i) if *i >= 0 => {
+            let i = u64::try_from(*i).map_err(|_| GxtError::Invalid)?;
+            write_head(out, UNSIGNED, i);
+        }
+        CborValue::Integer(i) => {
+            let i = u64::try_from(-1 - *i).map_err(|_| GxtError::Invalid)?;
+            write_head(out, NEGATIVE, i);
+        }
+        CborValue::Float(f) => write_float(out, *f),
+        CborValue::Bytes(bytes) => {
+            write_head(out, BYTES, bytes.len() as u64);
+            out.extend_from_slice(bytes);
+        }
+        CborValue::Text(text) => {
+            write_head(out, TEXT, text.len() as u64);
+            out.extend_from_slice(text.as_bytes());
+        }
+        CborValue::Array(values) => {
+            write_head(out, ARRAY, values.len() as u64);
+            for value in values {
+                write_value(out, value)?;
+            }
+        }
+        CborValue::Map(map) => {
+            let mut entries = map
+                .iter()
+                .map(|(key, value)| Ok((to_vec(key)?, value)))
+                .collect::<Result<Vec<_>, GxtError>>()?;
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            if entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+                return Err(GxtError::Invalid);
+            }
+            write_head(out, MAP, entries.len() as u64);
+            for (key, value) in entries {
+                out.extend_from_slice(&key);
+                write_value(out, value)?;
+            }
+        }
+        CborValue::Tag(tag, value) => {
+            write_head(out, TAG, *tag);
+            write_value(out, value)?;
+        }
+        _ => return Err(GxtError::Invalid),
+    }
+    Ok(())
+}
+
+/// Writes the initial byte and the shortest argument for the major type.
+fn write_head(out: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    match argument {
+        0..24 => out.push(major | argument as u8),
+        24..0x100 => out.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..0x1_0000 => {
+            out.push(major | 25);
+            out.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x1_0000..0x1_0000_0000 => {
+            out.push(major | 26);
+            out.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+#[allow(clippy::float_cmp)]
+fn write_float(out: &mut Vec<u8>, f: f64) {
+    let simple = SIMPLE << 5;
+    if f.is_nan() {
+        out.extend_from_slice(&[simple | 25, 0x7e, 0x00]);
+        return;
+    }
+    let single = f as f32;
+    if f64::from(single) != f {
+        out.push(simple | 27);
+        out.extend_from_slice(&f.to_bits().to_be_bytes());
+        return;
+    }
+    match half_bits(single) {
+        Some(half) => {
+            out.push(simple | 25);
+            out.extend_from_slice(&half.to_be_bytes());
+        }
+        None => {
+            out.push(simple | 26);
+            out.extend_from_slice(&single.to_bits().to_be_bytes());
+        }
+    }
+}
+
+/// The bits of the half precision float with exactly the same value, if there is one.
+fn half_bits(f: f32) -> Option<u16> {
+    let bits = f.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    match exponent {
+        // zero, single precision subnormals are too small for half precision
+        0 => (mantissa == 0).then_some(sign),
+        // infinity, NaN is handled by the caller
+        0xff => Some(sign | 0x7c00),
+        _ => {
+            let exponent = exponent - 127;
+            let significand = mantissa | 0x80_0000;
+            match exponent {
+                -14..=15 => (mantissa & 0x1fff == 0)
+                    .then(|| sign | (((exponent + 15) as u16) << 10) | (mantissa >> 13) as u16),
+                // half precision subnormals are multiples of 2^-24
+                -24..=-15 => {
+                    let shift = -exponent - 1;
+                    (significand & ((1 << shift) - 1) == 0)
+                        .then(|| sign | (significand >> shift) as u16)
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn hex(value: &CborValue) -> String {
+        hex::encode(to_vec(value).unwrap())
+    }
+
+    #[test]
+    fn integers_use_the_shortest_head() {
+        for (i, expected) in [
+            (0, "00"),
+            (23, "17"),
+            (24, "1818"),
+            (255, "18ff"),
+            (256, "190100"),
+            (65_536, "1a00010000"),
+            (1 << 32, "1b0000000100000000"),
+            (-1, "20"),
+            (-25, "3818"),
+            (-(1 << 64), "3bffffffffffffffff"),
+        ] {
+            assert_eq!(hex(&CborValue::Integer(i)), expected, "{i}");
+        }
+        assert!(to_vec(&CborValue::Integer(1 << 64)).is_err());
+        assert!(to_vec(&CborValue::Integer(-(1 << 64) - 1)).is_err());
+    }
+
+    #[test]
+    fn floats_use_the_shortest_precision_that_keeps_the_value() {
+        for (f, expected) in [
+            (0.0, "f90000"),
+            (-0.0, "f98000"),
+            (1.5, "f93e00"),
+            (65_504.0, "f97bff"),
+            (5.960_464_477_539_063e-8, "f90001"),
+            (100_000.0, "fa47c35000"),
+            (f64::from(f32::MAX), "fa7f7fffff"),
+            (1.1, "fb3ff199999999999a"),
+            (1e300, "fb7e37e43c8800759c"),
+            (f64::INFINITY, "f97c00"),
+            (f64::NEG_INFINITY, "f9fc00"),
+            (f64::NAN, "f97e00"),
+            (-f64::NAN, "f97e00"),
+        ] {
+            assert_eq!(hex(&CborValue::Float(f)), expected, "{f}");
+        }
+    }
+
+    #[test]
+    fn map_keys_are_sorted_by_their_encoding() {
+        let map = BTreeMap::from([
+            (CborValue::Text("aa".into()), CborValue::Integer(1)),
+            (CborValue::Text("b".into()), CborValue::Integer(2)),
+            (CborValue::Integer(-1), CborValue::Integer(3)),
+            (CborValue::Integer(10), CborValue::Integer(4)),
+            (CborValue::Integer(100), CborValue::Integer(5)),
+        ]);
+        // the encoded keys 0a, 1864, 20, 6162 and 626161 in bytewise order,
+        // which puts the shorter text first, but not the shorter integer
+        assert_eq!(hex(&CborValue::Map(map)), "a50a04186405200361620262616101");
+    }
+
+    #[test]
+    fn maps_inside_arrays_are_sorted_as_well() {
+        let map = BTreeMap::from([
+            (CborValue::Text("to".into()), CborValue::Null),
+            (CborValue::Text("enc".into()), CborValue::Null),
+        ]);
+        assert_eq!(
+            hex(&CborValue::Array(vec![CborValue::Map(map)])),
+            "81a262746ff663656e63f6"
+        );
+    }
+
+    #[test]
+    fn strings_and_tags_have_definite_lengths() {
+        assert_eq!(hex(&CborValue::Text("é".into())), "62c3a9");
+        assert_eq!(
+            hex(&CborValue::Bytes(vec![0xab; 24])),
+            format!("5818{}", "ab".repeat(24))
+        );
+        assert_eq!(
+            hex(&CborValue::Array(vec![CborValue::Null; 24])),
+            format!("9818{}", "f6".repeat(24))
+        );
+        assert_eq!(
+            hex(&CborValue::Tag(1, Box::new(CborValue::Integer(0)))),
+            "c100"
+        );
+    }
+}
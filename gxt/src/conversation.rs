@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use crate::{Envelope, JsonValue, PayloadKind};
+
+/// A problem found while checking the parent links of a [`Conversation`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConversationIssue {
+    /// The parent of a message is not part of the conversation.
+    MissingParent {
+        /// Id of the message
+        id: String,
+        /// Id of the parent that could not be found
+        parent: String,
+    },
+    /// The same key signed more than one message answering the same parent.
+    ///
+    /// Several players answering the same message, e.g. offers to a public request, are not a fork.
+    Fork {
+        /// Id of the parent
+        parent: String,
+        /// Hex encoded verification key that signed the replies
+        signer: String,
+        /// Ids of all messages of the signer answering the parent
+        replies: Vec<String>,
+    },
+    /// A reply was exchanged with keys that did not take part in its parent,
+    /// or without the sender of its parent.
+    WrongParticipants {
+        /// Id of the message
+        id: String,
+        /// Id of the parent
+        parent: String,
+    },
+    /// A reply claims to be created before its parent.
+    OutOfOrder {
+        /// Id of the message
+        id: String,
+        /// Id of the parent
+        parent: String,
+    },
+    /// The parent of a message is not covered by its signature (version 4 tokens).
+    UnauthenticatedParent {
+        /// Id of the message
+        id: String,
+        /// Id of the parent
+        parent: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    parent: Option<String>,
+    parent_authenticated: bool,
+    signer: String,
+    sender: String,
+    recipients: Option<BTreeSet<String>>,
+    created_at: Option<u64>,
+}
+
+/// A set of messages linked by their parent ids.
+///
+/// Add the **verified** envelopes of all messages belonging to a trade (see [`crate::verify_message`])
+/// and use [`Conversation::issues`] to check that every reply answers a known message
+/// and was exchanged between players of the message it answers.
+///
+/// Decrypted envelopes no longer contain the recipient of a message,
+/// so the participants of a reply can only be checked against verified envelopes.
+#[derive(Clone, Debug, Default)]
+pub struct Conversation {
+    messages: BTreeMap<String, Node>,
+}
+
+impl Conversation {
+    /// Creates an empty conversation.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a verified envelope to the conversation.
+    ///
+    /// Returns `false` if a message with the same id was already added.
+    pub fn insert(&mut self, envelope: &Envelope<JsonValue>) -> bool {
+        if self.messages.contains_key(&envelope.id) {
+            return false;
+        }
+        let recipients = match envelope.kind {
+            PayloadKind::Msg => match envelope.payload.get("to") {
+                Some(JsonValue::String(to)) => Some(BTreeSet::from([to.clone()])),
+                Some(JsonValue::Array(to)) => Some(
+                    to.iter()
+                        .filter_map(|recipient| recipient.get("key")?.as_str())
+                        .map(ToString::to_string)
+                        .collect(),
+                ),
+                _ => None,
+            },
+            _ => None,
+        };
+        self.messages.insert(
+            envelope.id.clone(),
+            Node {
+                parent: envelope.parent.clone(),
+                parent_authenticated: envelope.is_parent_authenticated(),
+                signer: envelope.verification_key.clone(),
+                sender: envelope.encryption_key.clone(),
+                recipients,
+                created_at: envelope.created_at,
+            },
+        );
+        true
+    }
+
+    /// Whether a message with the given id is part of the conversation.
+    pub fn contains(&self, id: &str) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    /// The number of messages in the conversation.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Whether the conversation contains no messages.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Ids of all messages without a parent.
+    pub fn roots(&self) -> Vec<&str> {
+        self.messages
+            .iter()
+            .filter(|(_, node)| node.parent.is_none())
+            .map(|(id, _)| id.as_str())
+            .collect()
+    }
+
+    /// Ids of all messages that answer the given message.
+    pub fn replies(&self, id: &str) -> Vec<&str> {
+        self.messages
+            .iter()
+            .filter(|(_, node)| node.parent.as_deref() == Some(id))
+            .map(|(id, _)| id.as_str())
+            .collect()
+    }
+
+    /// The chain of messages from the given message up to the first message of the conversation.
+    ///
+    /// The chain starts with the given id and stops at the first message that has no parent,
+    /// or whose parent is not part of the conversation.
+    pub fn chain<'a>(&'a self, id: &'a str) -> Vec<&'a str> {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = Some(id);
+        while let Some(id) = current {
+            let Some((id, node)) = self.messages.get_key_value(id) else {
+                break;
+            };
+            // Parents of version 4 tokens are unauthenticated and could form a cycle.
+            if !visited.insert(id.as_str()) {
+                break;
+            }
+            chain.push(id.as_str());
+            current = node.parent.as_deref();
+        }
+        chain
+    }
+
+    /// Whether `id` answers `parent` directly, with an authenticated parent link,
+    /// between keys of the parent including its sender, and without going back in time.
+    pub fn is_reply_to(&self, id: &str, parent: &str) -> bool {
+        let (Some(node), Some(parent_node)) = (self.messages.get(id), self.messages.get(parent))
+        else {
+            return false;
+        };
+        node.parent.as_deref() == Some(parent)
+            && node.parent_authenticated
+            && same_participants(node, parent_node)
+            && !is_out_of_order(node, parent_node)
+    }
+
+    /// Checks all parent links and reports every problem that was found.
+    pub fn issues(&self) -> Vec<ConversationIssue> {
+        let mut issues = Vec::new();
+        let mut replies: BTreeMap<(&str, &str), Vec<String>> = BTreeMap::new();
+
+        for (id, node) in &self.messages {
+            let Some(parent) = &node.parent else {
+                continue;
+            };
+            replies
+                .entry((parent, &node.signer))
+                .or_default()
+                .push(id.clone());
+
+            if !node.parent_authenticated {
+                issues.push(ConversationIssue::UnauthenticatedParent {
+                    id: id.clone(),
+                    parent: parent.clone(),
+                });
+            }
+
+            let Some(parent_node) = self.messages.get(parent) else {
+                issues.push(ConversationIssue::MissingParent {
+                    id: id.clone(),
+                    parent: parent.clone(),
+                });
+                continue;
+            };
+
+            if !same_participants(node, parent_node) {
+                issues.push(ConversationIssue::WrongParticipants {
+                    id: id.clone(),
+                    parent: parent.clone(),
+                });
+            }
+
+            if is_out_of_order(node, parent_node) {
+                issues.push(ConversationIssue::OutOfOrder {
+                    id: id.clone(),
+                    parent: parent.clone(),
+                });
+            }
+        }
+
+        for ((parent, signer), replies) in replies {
+            if replies.len() > 1 {
+                issues.push(ConversationIssue::Fork {
+                    parent: parent.to_string(),
+                    signer: signer.to_string(),
+                    replies,
+                });
+            }
+        }
+
+        issues
+    }
+}
+
+impl<'a> FromIterator<&'a Envelope<JsonValue>> for Conversation {
+    fn from_iter<T: IntoIterator<Item = &'a Envelope<JsonValue>>>(iter: T) -> Self {
+        let mut conversation = Self::new();
+        for envelope in iter {
+            conversation.insert(envelope);
+        }
+        conversation
+    }
+}
+
+/// A reply must be exchanged between the keys of its parent, and include the sender of the parent.
+/// This allows one recipient of a message for many to answer the sender privately.
+/// If the recipients of a message are unknown (e.g. for ID cards),
+/// its sender must at least take part in the other message.
+fn same_participants(reply: &Node, parent: &Node) -> bool {
+    match (participants(reply), participants(parent)) {
+        (Some(reply_participants), Some(parent_participants)) => {
+            reply_participants.is_subset(&parent_participants)
+                && reply_participants.contains(parent.sender.as_str())
+        }
+        (Some(reply_participants), None) => reply_participants.contains(parent.sender.as_str()),
+        (None, Some(parent_participants)) => parent_participants.contains(reply.sender.as_str()),
+        (None, None) => reply.sender == parent.sender,
+    }
+}
+
+/// The sender and all recipients of a message, if its recipients are known.
+fn participants(node: &Node) -> Option<BTreeSet<&str>> {
+    let recipients = node.recipients.as_ref()?;
+    Some(
+        recipients
+            .iter()
+            .map(String::as_str)
+            .chain([node.sender.as_str()])
+            .collect(),
+    )
+}
+
+fn is_out_of_order(reply: &Node, parent: &Node) -> bool {
+    matches!(
+        (reply.created_at, parent.created_at),
+        (Some(reply_created_at), Some(parent_created_at)) if reply_created_at < parent_created_at
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{IdCard, Identity, Token};
+
+    fn envelope(token: &Token) -> Envelope<JsonValue> {
+        token.verify().unwrap()
+    }
+
+    fn reply(from: &Identity, to: &IdCard, parent: &str) -> Envelope<JsonValue> {
+        envelope(
+            &from
+                .message()
+                .payload(&"offer")
+                .to(to)
+                .parent(parent)
+                .encrypt()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn replies_of_several_players_are_not_a_fork() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let carol = Identity::generate();
+        let alice_card = alice.make_id_card(()).unwrap();
+        let request = envelope(&alice.message().payload(&"request").sign().unwrap());
+        let replies = [
+            reply(&bob, &alice_card, &request.id),
+            reply(&carol, &alice_card, &request.id),
+        ];
+        let conversation = [&request, &replies[0], &replies[1]]
+            .into_iter()
+            .collect::<Conversation>();
+        assert_eq!(conversation.issues(), vec![]);
+        assert_eq!(conversation.replies(&request.id).len(), 2);
+    }
+
+    #[test]
+    fn two_replies_of_the_same_signer_are_a_fork() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let carol = Identity::generate();
+        let alice_card = alice.make_id_card(()).unwrap();
+        let request = envelope(&alice.message().payload(&"request").sign().unwrap());
+        let first = reply(&bob, &alice_card, &request.id);
+        let second = reply(&bob, &alice_card, &request.id);
+        let other = reply(&carol, &alice_card, &request.id);
+        let conversation = [&request, &first, &second, &other]
+            .into_iter()
+            .collect::<Conversation>();
+        let mut forked = vec![first.id.clone(), second.id.clone()];
+        forked.sort();
+        assert_eq!(
+            conversation.issues(),
+            vec![ConversationIssue::Fork {
+                parent: request.id.clone(),
+                signer: bob.verification_key(),
+                replies: forked,
+            }]
+        );
+    }
+
+    /// The envelope as if it was a version 4 token answering `parent`, which did not sign the parent.
+    fn with_unauthenticated_parent(
+        envelope: &Envelope<JsonValue>,
+        parent: &str,
+    ) -> Envelope<JsonValue> {
+        Envelope {
+            version: 4,
+            parent: Some(parent.to_string()),
+            ..envelope.clone()
+        }
+    }
+
+    #[test]
+    fn messages_are_only_added_once() {
+        let alice = Identity::generate();
+        let request = envelope(&alice.message().payload(&"request").sign().unwrap());
+        let mut conversation = Conversation::new();
+        assert!(conversation.insert(&request));
+        assert!(!conversation.insert(&request));
+        assert_eq!(conversation.len(), 1);
+        assert_eq!(conversation.roots(), vec![request.id.as_str()]);
+    }
+
+    #[test]
+    fn replies_to_unknown_messages_are_reported() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let missing = "00".repeat(32);
+        let orphan = reply(&bob, &alice.make_id_card(()).unwrap(), &missing);
+        let conversation = [&orphan].into_iter().collect::<Conversation>();
+        assert_eq!(
+            conversation.issues(),
+            vec![ConversationIssue::MissingParent {
+                id: orphan.id.clone(),
+                parent: missing.clone(),
+            }]
+        );
+        assert_eq!(conversation.chain(&orphan.id), vec![orphan.id.as_str()]);
+        assert!(conversation.roots().is_empty());
+    }
+
+    #[test]
+    fn replies_between_other_players_are_reported() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let carol = Identity::generate();
+        let offer = reply(&bob, &alice.make_id_card(()).unwrap(), &"00".repeat(32));
+        let answer = reply(&alice, &carol.make_id_card(()).unwrap(), &offer.id);
+        let conversation = [&offer, &answer].into_iter().collect::<Conversation>();
+        assert!(
+            conversation
+                .issues()
+                .contains(&ConversationIssue::WrongParticipants {
+                    id: answer.id.clone(),
+                    parent: offer.id.clone(),
+                })
+        );
+        assert!(!conversation.is_reply_to(&answer.id, &offer.id));
+    }
+
+    #[test]
+    fn replies_created_before_their_parent_are_reported() {
+        let alice = Identity::generate();
+        let request = envelope(&alice.message().payload(&"request").sign().unwrap());
+        let request = Envelope {
+            created_at: Some(2_000),
+            ..request
+        };
+        let answer = envelope(
+            &alice
+                .message()
+                .payload(&"answer")
+                .parent(&request.id)
+                .validity(crate::Validity {
+                    created_at: Some(1_000),
+                    expires_at: None,
+                })
+                .sign()
+                .unwrap(),
+        );
+        let conversation = [&request, &answer].into_iter().collect::<Conversation>();
+        assert_eq!(
+            conversation.issues(),
+            vec![ConversationIssue::OutOfOrder {
+                id: answer.id.clone(),
+                parent: request.id.clone(),
+            }]
+        );
+        assert!(!conversation.is_reply_to(&answer.id, &request.id));
+    }
+
+    #[test]
+    fn unauthenticated_parents_are_reported_and_cycles_end_the_chain() {
+        let alice = Identity::generate();
+        let first = envelope(&alice.message().payload(&"first").sign().unwrap());
+        let second = envelope(&alice.message().payload(&"second").sign().unwrap());
+        let first = with_unauthenticated_parent(&first, &second.id);
+        let second = with_unauthenticated_parent(&second, &first.id);
+        let conversation = [&first, &second].into_iter().collect::<Conversation>();
+        assert_eq!(
+            conversation.chain(&first.id),
+            vec![first.id.as_str(), second.id.as_str()]
+        );
+        assert!(!conversation.is_reply_to(&first.id, &second.id));
+        let issues = conversation.issues();
+        assert_eq!(issues.len(), 2);
+        assert!(
+            issues
+                .iter()
+                .all(|issue| matches!(issue, ConversationIssue::UnauthenticatedParent { .. }))
+        );
+    }
+
+    #[test]
+    fn private_replies_to_a_message_for_many_are_allowed() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let carol = Identity::generate();
+        let dave = Identity::generate();
+        let cards = [&bob, &carol, &dave].map(|identity| identity.make_id_card(()).unwrap());
+        let offer = envelope(
+            &alice
+                .message()
+                .payload(&"offer")
+                .to_all(&cards)
+                .encrypt()
+                .unwrap(),
+        );
+        let private = reply(&bob, &alice.make_id_card(()).unwrap(), &offer.id);
+        let without_sender = reply(&carol, &cards[2], &offer.id);
+        let outsider = reply(
+            &bob,
+            &Identity::generate().make_id_card(()).unwrap(),
+            &offer.id,
+        );
+        let conversation = [&offer, &private, &without_sender, &outsider]
+            .into_iter()
+            .collect::<Conversation>();
+        assert!(conversation.is_reply_to(&private.id, &offer.id));
+        assert!(!conversation.is_reply_to(&without_sender.id, &offer.id));
+        assert!(!conversation.is_reply_to(&outsider.id, &offer.id));
+        let wrong = conversation
+            .issues()
+            .into_iter()
+            .filter_map(|issue| match issue {
+                ConversationIssue::WrongParticipants { id, .. } => Some(id),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        assert_eq!(
+            wrong,
+            BTreeSet::from([without_sender.id.clone(), outsider.id.clone()])
+        );
+    }
+}
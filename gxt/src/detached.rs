@@ -0,0 +1,153 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Read},
+    str::FromStr,
+};
+
+use serde_cbor::Value as CborValue;
+
+use crate::{Bytes32, Envelope, GxtError, PayloadKind, ProtocolVersion, Token, parse_hex};
+
+const HASH_ALGORITHM: &str = "blake3";
+
+/// A verified signature over data that is too large to be put into a token,
+/// e.g. save files or mod configs.
+///
+/// The token only contains the BLAKE3 hash and the size of the data,
+/// so the data has to be passed to [`DetachedSignature::verify`] separately.
+#[derive(Clone, Debug)]
+pub struct DetachedSignature {
+    token: Token,
+    envelope: Envelope<CborValue>,
+    hash: Bytes32,
+    size: u64,
+}
+
+impl DetachedSignature {
+    /// The `gxs:` token of this signature.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// The id of this signature.
+    pub fn id(&self) -> &str {
+        &self.envelope.id
+    }
+
+    /// The hex encoded verification key of the signer.
+    pub fn verification_key(&self) -> &str {
+        &self.envelope.verification_key
+    }
+
+    /// The hex encoded BLAKE3 hash of the signed data.
+    pub fn hash(&self) -> String {
+        hex::encode(self.hash)
+    }
+
+    /// The size of the signed data in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The verified envelope of this signature.
+    pub fn envelope(&self) -> &Envelope<CborValue> {
+        &self.envelope
+    }
+
+    /// Checks that the data is the data that was signed.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::HashMismatch`] if the data is different.
+    pub fn verify(&self, data: &[u8]) -> Result<(), GxtError> {
+        self.verify_reader(data)
+    }
+
+    /// Checks that the data read from the reader is the data that was signed,
+    /// without loading all of it into memory.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::HashMismatch`] if the data is different.
+    /// - returns a corresponding [`GxtError`], if reading fails.
+    pub fn verify_reader<R: Read>(&self, reader: R) -> Result<(), GxtError> {
+        let (hash, size) = hash_reader(reader)?;
+        if hash != self.hash || size != self.size {
+            return Err(GxtError::HashMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DetachedSignature {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Token>()?.try_into()
+    }
+}
+
+impl TryFrom<Token> for DetachedSignature {
+    type Error = GxtError;
+
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        if token.kind() != PayloadKind::Signature {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::Signature,
+                got: token.kind(),
+            });
+        }
+        let envelope = token.verify::<CborValue>()?;
+        let CborValue::Map(map) = &envelope.payload else {
+            return Err(GxtError::Invalid);
+        };
+        match map.get(&CborValue::Text("alg".into())) {
+            Some(CborValue::Text(t)) if t == HASH_ALGORITHM => {}
+            _ => return Err(GxtError::Invalid),
+        }
+        let hash = match map.get(&CborValue::Text("hash".into())) {
+            Some(CborValue::Text(t)) => parse_hex::<32>(t)?,
+            _ => return Err(GxtError::Invalid),
+        };
+        let size = match map.get(&CborValue::Text("size".into())) {
+            Some(CborValue::Integer(i)) => u64::try_from(*i).map_err(|_| GxtError::Invalid)?,
+            _ => return Err(GxtError::Invalid),
+        };
+        Ok(Self {
+            token,
+            envelope,
+            hash,
+            size,
+        })
+    }
+}
+
+impl fmt::Display for DetachedSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.token.fmt(f)
+    }
+}
+
+/// Hashes the data and creates the payload of a detached signature.
+pub(crate) fn signature_payload<R: Read>(reader: R) -> Result<CborValue, GxtError> {
+    let (hash, size) = hash_reader(reader)?;
+    let mut signature = BTreeMap::new();
+    signature.insert(
+        CborValue::Text("alg".into()),
+        CborValue::Text(HASH_ALGORITHM.into()),
+    );
+    signature.insert(
+        CborValue::Text("hash".into()),
+        ProtocolVersion::for_new_tokens().bytes_value(&hash),
+    );
+    signature.insert(
+        CborValue::Text("size".into()),
+        CborValue::Integer(size.into()),
+    );
+    Ok(CborValue::Map(signature))
+}
+
+fn hash_reader<R: Read>(mut reader: R) -> Result<(Bytes32, u64), GxtError> {
+    let mut hasher = blake3::Hasher::new();
+    let size = io::copy(&mut reader, &mut hasher)?;
+    Ok((*hasher.finalize().as_bytes(), size))
+}
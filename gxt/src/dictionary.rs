@@ -0,0 +1,219 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    ops::RangeInclusive,
+    sync::{Arc, LazyLock, PoisonError, RwLock},
+};
+
+use serde::Serialize;
+
+use crate::GxtError;
+
+/// Ids reserved for the dictionaries that ship with this crate ("GX" in the upper half).
+const BUILTIN_IDS: RangeInclusive<u32> = 0x4758_0000..=0x4758_ffff;
+
+/// The built-in dictionaries, from oldest to newest.
+///
+/// A dictionary must never be changed or removed once released, otherwise
+/// tokens that were compressed with it can no longer be decoded.
+const BUILTINS: [&[u8]; 1] = [include_bytes!("../dictionaries/gxt-1.zdict")];
+
+/// Compression level used for all tokens.
+const LEVEL: i32 = 3;
+
+static BUILTIN_DICTIONARIES: LazyLock<Vec<Dictionary>> = LazyLock::new(|| {
+    BUILTINS
+        .iter()
+        .map(|data| Dictionary::parse(data).expect("built-in dictionaries are valid"))
+        .collect()
+});
+
+static REGISTRY: LazyLock<RwLock<Registry>> = LazyLock::new(|| {
+    RwLock::new(Registry {
+        custom: BTreeMap::new(),
+        compression: Some(Dictionary::builtin()),
+    })
+});
+
+struct Registry {
+    custom: BTreeMap<u32, Dictionary>,
+    compression: Option<Dictionary>,
+}
+
+/// A zstd dictionary, which makes short tokens a lot smaller by providing
+/// the field names and values that typical payloads share.
+///
+/// The id of the dictionary is stored in the header of the compressed token,
+/// so a token can be decoded by everyone who knows the dictionary, without any further hints.
+/// The built-in dictionaries are always known, custom ones have to be registered
+/// with [`register_dictionary`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    data: Arc<[u8]>,
+}
+
+impl Dictionary {
+    /// The newest built-in dictionary, which was trained on the payloads of [`crate::advisory`].
+    ///
+    /// It is used for new tokens, unless [`set_compression_dictionary`] selects a different one.
+    pub fn builtin() -> Self {
+        Self::builtins()
+            .last()
+            .expect("there is at least one built-in dictionary")
+            .clone()
+    }
+
+    /// All built-in dictionaries, from oldest to newest.
+    pub fn builtins() -> &'static [Self] {
+        &BUILTIN_DICTIONARIES
+    }
+
+    /// Loads a dictionary that was trained with [`Dictionary::train`] or `zstd --train`.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::InvalidDictionary`] if the data is not a zstd dictionary,
+    ///   or if its id is reserved for the built-in dictionaries.
+    pub fn new(data: &[u8]) -> Result<Self, GxtError> {
+        let dictionary = Self::parse(data)?;
+        if BUILTIN_IDS.contains(&dictionary.id) {
+            return Err(GxtError::InvalidDictionary);
+        }
+        Ok(dictionary)
+    }
+
+    /// Trains a dictionary for the payloads of a game.
+    ///
+    /// The payloads should be typical for the tokens the game creates, e.g. a few thousand
+    /// trade orders with realistic items. Dictionaries of a few KiB work best for short tokens.
+    ///
+    /// The `id` identifies the dictionary in every token that was compressed with it,
+    /// so it should be unique among all dictionaries the game ever uses.
+    /// Ids from 32768 to 2^31 - 1 are free to use, except the ones reserved for the built-in dictionaries.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::InvalidDictionary`] if the id is 0 or reserved.
+    /// - returns [`GxtError::Compression`] if there are not enough payloads to train a dictionary.
+    pub fn train<P: Serialize>(payloads: &[P], max_size: usize, id: u32) -> Result<Self, GxtError> {
+        if id == 0 || BUILTIN_IDS.contains(&id) {
+            return Err(GxtError::InvalidDictionary);
+        }
+        let mut samples = Vec::with_capacity(payloads.len() * 2);
+        for payload in payloads {
+            // Signed payloads are stored as CBOR values with sorted map keys,
+            // encrypted ones with the fields in the order they were serialized in.
+            samples.push(serde_cbor::to_vec(payload)?);
+            samples.push(serde_cbor::to_vec(&serde_cbor::value::to_value(payload)?)?);
+        }
+        let mut data = zstd::dict::from_samples(&samples, max_size)?;
+        // The id follows the four byte magic number.
+        data[4..8].copy_from_slice(&id.to_le_bytes());
+        Self::parse(&data)
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, GxtError> {
+        let id = zstd::zstd_safe::get_dict_id_from_dict(data).ok_or(GxtError::InvalidDictionary)?;
+        Ok(Self {
+            id: id.get(),
+            data: data.into(),
+        })
+    }
+
+    /// The id, which is stored in every token that was compressed with this dictionary.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The dictionary in the format of zstd, e.g. to save it to a file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether this is one of the built-in dictionaries.
+    pub fn is_builtin(&self) -> bool {
+        BUILTIN_IDS.contains(&self.id)
+    }
+}
+
+impl fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dictionary")
+            .field("id", &format_args!("{:#010x}", self.id))
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+/// Makes a custom dictionary known, so tokens that were compressed with it can be decoded.
+///
+/// Registering the same dictionary twice does nothing.
+///
+/// # Errors
+/// - returns [`GxtError::DictionaryConflict`] if a different dictionary with the same id is already known.
+pub fn register_dictionary(dictionary: Dictionary) -> Result<(), GxtError> {
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    register(&mut registry, dictionary)
+}
+
+/// Selects the dictionary that new tokens are compressed with, and registers it.
+///
+/// `None` compresses new tokens without a dictionary, like tokens created before
+/// dictionaries were introduced. The default is [`Dictionary::builtin`].
+///
+/// # Errors
+/// - returns [`GxtError::DictionaryConflict`] if a different dictionary with the same id is already known.
+pub fn set_compression_dictionary(dictionary: Option<Dictionary>) -> Result<(), GxtError> {
+    let mut registry = REGISTRY.write().unwrap_or_else(PoisonError::into_inner);
+    if let Some(dictionary) = &dictionary {
+        register(&mut registry, dictionary.clone())?;
+    }
+    registry.compression = dictionary;
+    Ok(())
+}
+
+/// The dictionary that new tokens are compressed with, see [`set_compression_dictionary`].
+pub fn compression_dictionary() -> Option<Dictionary> {
+    REGISTRY
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .compression
+        .clone()
+}
+
+fn register(registry: &mut Registry, dictionary: Dictionary) -> Result<(), GxtError> {
+    match find(registry, dictionary.id) {
+        Some(known) if known == dictionary => Ok(()),
+        Some(_) => Err(GxtError::DictionaryConflict(dictionary.id)),
+        None => {
+            registry.custom.insert(dictionary.id, dictionary);
+            Ok(())
+        }
+    }
+}
+
+fn find(registry: &Registry, id: u32) -> Option<Dictionary> {
+    if BUILTIN_IDS.contains(&id) {
+        return Dictionary::builtins()
+            .iter()
+            .find(|dictionary| dictionary.id == id)
+            .cloned();
+    }
+    registry.custom.get(&id).cloned()
+}
+
+/// Looks up the dictionary with the given id.
+pub(crate) fn dictionary(id: u32) -> Result<Dictionary, GxtError> {
+    let registry = REGISTRY.read().unwrap_or_else(PoisonError::into_inner);
+    find(&registry, id).ok_or(GxtError::UnknownDictionary(id))
+}
+
+/// Compresses the data with the dictionary.
+pub(crate) fn compress(data: &[u8], dictionary: Option<&Dictionary>) -> Result<Vec<u8>, GxtError> {
+    match dictionary {
+        Some(dictionary) => {
+            let mut compressor = zstd::bulk::Compressor::with_dictionary(LEVEL, &dictionary.data)?;
+            Ok(compressor.compress(data)?)
+        }
+        None => Ok(zstd::encode_all(data, LEVEL)?),
+    }
+}
@@ -0,0 +1,389 @@
+use std::{fmt, str::FromStr};
+
+use data_encoding::{BASE32_NOPAD, BASE64URL_NOPAD};
+use serde::{Deserialize, Serialize};
+
+use crate::{GxtError, PREFIX, PayloadKind, kind_code};
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+/// Number of Base58 digits that are added to the number at once, as `58^10 < 2^64`.
+const BASE58_DIGITS_PER_STEP: usize = 10;
+const BECH32_ALPHABET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_SEPARATOR: char = '1';
+const BECH32M_CONSTANT: u32 = 0x2bc8_30a3;
+const BECH32_CHECKSUM_LEN: usize = 6;
+
+/// How the compressed token is written as text.
+///
+/// The encoding is part of the prefix, so a token can always be decoded
+/// without knowing how it was encoded. The id and signature do not depend on it,
+/// so a token can be re-encoded with [`crate::Token::to_encoding`] without signing it again.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// Base58 with the prefix `gxm:`
+    #[default]
+    Base58,
+    /// Upper case Base32 with the prefix `GXM:`, which only uses characters
+    /// of the alphanumeric mode of QR codes and is easy to read out loud
+    Qr,
+    /// URL-safe Base64 without padding with the prefix `gxm64:`
+    Base64Url,
+    /// Bech32 characters with the prefix `gxm1` and a six character checksum,
+    /// so most typos are detected before the token is decoded
+    ///
+    /// Bech32 only guarantees to detect up to four wrong characters in strings of up to 90 characters.
+    /// Tokens are longer, so the checksum is only a convenience, and the integrity of the token
+    /// still relies on its signature and, for encrypted messages, the AEAD tag.
+    Bech32,
+}
+
+impl Encoding {
+    /// All encodings, starting with the default.
+    pub fn all() -> &'static [Encoding] {
+        &[Self::Base58, Self::Qr, Self::Base64Url, Self::Bech32]
+    }
+
+    /// The maximum number of characters that `size` bytes are encoded into, without the prefix.
+    pub(crate) fn max_encoded_len(self, size: usize) -> usize {
+        match self {
+            // Every byte takes at most log(256) / log(58) < 1.366 characters.
+            Self::Base58 => size.saturating_mul(1366) / 1000 + 1,
+            Self::Qr => size.saturating_mul(8).div_ceil(5),
+            Self::Base64Url => size.saturating_mul(4).div_ceil(3),
+            Self::Bech32 => size.saturating_mul(8).div_ceil(5) + BECH32_CHECKSUM_LEN,
+        }
+    }
+
+    /// Whether the character can appear in the data of a token with this encoding.
+    pub(crate) fn is_alphabet(self, c: u8) -> bool {
+        match self {
+            Self::Base58 => BASE58_ALPHABET.contains(&c),
+            Self::Qr => c.is_ascii_uppercase() || (b'2'..=b'7').contains(&c),
+            Self::Base64Url => c.is_ascii_alphanumeric() || c == b'-' || c == b'_',
+            Self::Bech32 => BECH32_ALPHABET.contains(&c),
+        }
+    }
+
+    pub(crate) fn encode(self, kind: PayloadKind, data: &[u8]) -> String {
+        let code = kind_code(kind);
+        match self {
+            Self::Base58 => format!("{PREFIX}{code}:{}", bs58::encode(data).into_string()),
+            Self::Qr => format!(
+                "{}{}:{}",
+                PREFIX.to_ascii_uppercase(),
+                code.to_ascii_uppercase(),
+                BASE32_NOPAD.encode(data)
+            ),
+            Self::Base64Url => format!("{PREFIX}{code}64:{}", BASE64URL_NOPAD.encode(data)),
+            Self::Bech32 => {
+                let hrp = format!("{PREFIX}{code}");
+                let mut values = to_five_bits(data);
+                values.extend(bech32_checksum(&hrp, &values));
+                let body = values
+                    .into_iter()
+                    .map(|value| char::from(BECH32_ALPHABET[usize::from(value)]))
+                    .collect::<String>();
+                format!("{hrp}{BECH32_SEPARATOR}{body}")
+            }
+        }
+    }
+
+    pub(crate) fn decode(self, kind: PayloadKind, body: &str) -> Result<Vec<u8>, GxtError> {
+        match self {
+            Self::Base58 => Ok(decode_base58(body)?),
+            Self::Qr => Ok(BASE32_NOPAD.decode(body.as_bytes())?),
+            Self::Base64Url => Ok(BASE64URL_NOPAD.decode(body.as_bytes())?),
+            Self::Bech32 => {
+                let hrp = format!("{PREFIX}{}", kind_code(kind));
+                let values = body
+                    .bytes()
+                    .map(|c| {
+                        BECH32_ALPHABET
+                            .iter()
+                            .position(|&a| a == c)
+                            .and_then(|value| u8::try_from(value).ok())
+                            .ok_or(GxtError::BadChecksum)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let Some(data_len) = values.len().checked_sub(BECH32_CHECKSUM_LEN) else {
+                    return Err(GxtError::BadChecksum);
+                };
+                let (data, checksum) = values.split_at(data_len);
+                if bech32_checksum(&hrp, data) != checksum {
+                    return Err(GxtError::BadChecksum);
+                }
+                from_five_bits(data)
+            }
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "base58" => Ok(Self::Base58),
+            "qr" => Ok(Self::Qr),
+            "base64url" => Ok(Self::Base64Url),
+            "bech32" => Ok(Self::Bech32),
+            _ => Err(GxtError::UnknownEncoding),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Base58 => write!(f, "base58"),
+            Self::Qr => write!(f, "qr"),
+            Self::Base64Url => write!(f, "base64url"),
+            Self::Bech32 => write!(f, "bech32"),
+        }
+    }
+}
+
+/// Splits a token into its payload kind, its encoding and the encoded data.
+pub(crate) fn split_token(token: &str) -> Result<(PayloadKind, Encoding, &str), GxtError> {
+    if let Some(rest) = token.strip_prefix(&PREFIX.to_ascii_uppercase()) {
+        let (code, body) = rest.split_once(':').ok_or(GxtError::BadPrefix)?;
+        if code.bytes().any(|c| c.is_ascii_lowercase()) {
+            return Err(GxtError::BadPrefix);
+        }
+        return Ok((code.to_ascii_lowercase().parse()?, Encoding::Qr, body));
+    }
+    let rest = token.strip_prefix(PREFIX).ok_or(GxtError::BadPrefix)?;
+    if let Some((code, body)) = rest.split_once(':') {
+        return match code.strip_suffix("64") {
+            Some(code) => Ok((code.parse()?, Encoding::Base64Url, body)),
+            None => Ok((code.parse()?, Encoding::Base58, body)),
+        };
+    }
+    let (code, body) = rest
+        .split_once(BECH32_SEPARATOR)
+        .ok_or(GxtError::BadPrefix)?;
+    Ok((code.parse()?, Encoding::Bech32, body))
+}
+
+/// Decodes Base58 like [`bs58::decode`], but adds ten digits at a time to 64 bit limbs
+/// instead of one digit to single bytes.
+///
+/// Decoding Base58 takes quadratic time, so this makes a difference of seconds for the longest tokens.
+fn decode_base58(body: &str) -> Result<Vec<u8>, bs58::decode::Error> {
+    let digits = body
+        .bytes()
+        .enumerate()
+        .map(|(index, c)| {
+            if !c.is_ascii() {
+                return Err(bs58::decode::Error::NonAsciiCharacter { index });
+            }
+            BASE58_ALPHABET
+                .iter()
+                .position(|&a| a == c)
+                .map(|digit| digit as u64)
+                .ok_or(bs58::decode::Error::InvalidCharacter {
+                    character: char::from(c),
+                    index,
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    // Every leading zero digit stands for a zero byte.
+    let zeros = digits.iter().take_while(|&&digit| digit == 0).count();
+    // The number in little endian order
+    let mut limbs = Vec::<u64>::with_capacity(digits.len() / BASE58_DIGITS_PER_STEP + 1);
+    for step in digits[zeros..].chunks(BASE58_DIGITS_PER_STEP) {
+        let factor = u128::from(58u64.pow(step.len() as u32));
+        let mut carry = step.iter().fold(0u64, |value, &digit| value * 58 + digit);
+        for limb in &mut limbs {
+            let value = u128::from(*limb) * factor + u128::from(carry);
+            *limb = value as u64;
+            carry = (value >> 64) as u64;
+        }
+        if carry > 0 {
+            limbs.push(carry);
+        }
+    }
+    let mut data = vec![0u8; zeros];
+    data.extend(
+        limbs
+            .iter()
+            .rev()
+            .flat_map(|limb| limb.to_be_bytes())
+            .skip_while(|&byte| byte == 0),
+    );
+    Ok(data)
+}
+
+fn to_five_bits(data: &[u8]) -> Vec<u8> {
+    let mut values = Vec::with_capacity((data.len() * 8).div_ceil(5));
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | u16::from(byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            values.push(((buffer >> bits) & 31) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        values.push(((buffer << (5 - bits)) & 31) as u8);
+    }
+    values
+}
+
+fn from_five_bits(values: &[u8]) -> Result<Vec<u8>, GxtError> {
+    let mut data = Vec::with_capacity(values.len() * 5 / 8);
+    let mut buffer = 0u16;
+    let mut bits = 0;
+    for &value in values {
+        buffer = (buffer << 5) | u16::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((buffer >> bits) as u8);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    // Only the zero padding of the last byte may be left over.
+    if bits >= 5 || buffer != 0 {
+        return Err(GxtError::BadChecksum);
+    }
+    Ok(data)
+}
+
+fn bech32_checksum(hrp: &str, values: &[u8]) -> [u8; BECH32_CHECKSUM_LEN] {
+    let hrp_values = hrp
+        .bytes()
+        .map(|c| c >> 5)
+        .chain([0])
+        .chain(hrp.bytes().map(|c| c & 31));
+    let polymod = bech32_polymod(
+        hrp_values
+            .chain(values.iter().copied())
+            .chain([0; BECH32_CHECKSUM_LEN]),
+    ) ^ BECH32M_CONSTANT;
+    std::array::from_fn(|i| ((polymod >> (5 * (BECH32_CHECKSUM_LEN - 1 - i))) & 31) as u8)
+}
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+    let mut checksum = 1u32;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ff_ffff) << 5) ^ u32::from(value);
+        for (i, generator) in GENERATOR.iter().enumerate() {
+            if (top >> i) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples() -> Vec<Vec<u8>> {
+        let mut samples = vec![
+            vec![],
+            vec![0],
+            vec![0, 0, 0, 1],
+            vec![0xff; 100],
+            vec![0; 100],
+        ];
+        samples.extend((1..200).map(|len| {
+            (0..len)
+                .map(|i: usize| (i.wrapping_mul(7919) ^ len) as u8)
+                .collect()
+        }));
+        samples
+    }
+
+    #[test]
+    fn base58_matches_bs58() {
+        for data in samples() {
+            let encoded = bs58::encode(&data).into_string();
+            assert_eq!(decode_base58(&encoded).unwrap(), data, "{encoded}");
+        }
+        for body in ["", "1", "111z", "zzzzzzzzzzzzzzzzzzzzzzzzz", "1zz1"] {
+            assert_eq!(
+                decode_base58(body).unwrap(),
+                bs58::decode(body).into_vec().unwrap(),
+                "{body}"
+            );
+        }
+    }
+
+    #[test]
+    fn base58_reports_the_same_errors_as_bs58() {
+        for body in ["abc0", "ab l", "abcé", "O"] {
+            assert_eq!(
+                decode_base58(body).unwrap_err(),
+                bs58::decode(body).into_vec().unwrap_err(),
+                "{body}"
+            );
+        }
+    }
+
+    #[test]
+    fn max_encoded_len_is_an_upper_bound() {
+        for &encoding in Encoding::all() {
+            for data in samples() {
+                let token = encoding.encode(PayloadKind::Msg, &data);
+                let (_, _, body) = split_token(&token).unwrap();
+                assert!(
+                    body.len() <= encoding.max_encoded_len(data.len()),
+                    "{encoding} {}",
+                    data.len()
+                );
+                assert_eq!(encoding.decode(PayloadKind::Msg, body).unwrap(), data);
+            }
+        }
+    }
+
+    #[test]
+    fn split_token_detects_the_encoding() {
+        for &encoding in Encoding::all() {
+            let token = encoding.encode(PayloadKind::Id, b"data");
+            let (kind, detected, _) = split_token(&token).unwrap();
+            assert_eq!((kind, detected), (PayloadKind::Id, encoding), "{token}");
+        }
+        for token in ["gx", "gxm", "Gxm:abc", "GXm:ABC", "gxq:abc", "xgm:abc"] {
+            assert!(split_token(token).is_err(), "{token}");
+        }
+    }
+
+    #[test]
+    fn bech32_detects_typos() {
+        let token = Encoding::Bech32.encode(PayloadKind::Msg, b"some compressed data");
+        let (kind, _, body) = split_token(&token).unwrap();
+        for i in 0..body.len() {
+            let mut typo = body.as_bytes().to_vec();
+            typo[i] = if typo[i] == b'q' { b'p' } else { b'q' };
+            let typo = String::from_utf8(typo).unwrap();
+            assert!(matches!(
+                Encoding::Bech32.decode(kind, &typo),
+                Err(GxtError::BadChecksum)
+            ));
+        }
+        let mut swapped = body.as_bytes().to_vec();
+        swapped.swap(3, 4);
+        if swapped != body.as_bytes() {
+            let swapped = String::from_utf8(swapped).unwrap();
+            assert!(Encoding::Bech32.decode(kind, &swapped).is_err());
+        }
+        assert!(matches!(
+            Encoding::Bech32.decode(kind, "qqqqq"),
+            Err(GxtError::BadChecksum)
+        ));
+    }
+}
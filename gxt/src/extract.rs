@@ -0,0 +1,321 @@
+use crate::{
+    GxtError, Limits, PREFIX, PayloadKind, Token, TokenParts, decode_envelope_values, parts,
+    split_token,
+};
+
+/// Characters that are invisible, but are inserted by some chat clients and editors.
+const INVISIBLE: [char; 6] = [
+    '\u{00AD}', '\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}',
+];
+
+/// Number of ways to join a wrapped token that are tried, starting with the longest one.
+const MAX_CANDIDATES: usize = 8;
+/// Number of tokens of the maximum length that are decoded per call.
+///
+/// Decoding Base58 takes quadratic time, so every decoded candidate uses up its length squared
+/// of the budget, which allows many more short tokens.
+const MAX_DECODED_TOKENS: u64 = 4;
+/// Number of multi-part tokens whose parts are collected per call.
+const MAX_PART_GROUPS: usize = 16;
+/// Number of parts that are collected per call, of all multi-part tokens together.
+const MAX_PARTS: usize = 4096;
+
+/// Finds all tokens in arbitrary text, like chat logs, forum posts or markdown.
+///
+/// - Invisible characters, like zero-width spaces, are ignored.
+/// - Tokens can be surrounded by any punctuation, e.g. code fences, quotes or brackets.
+/// - A token that was wrapped onto following lines is joined again, as long as every
+///   line between its start and end contains nothing but the token (quote markers like `>` are ignored).
+/// - Parts of a multi-part token are joined once all of them were found.
+///
+/// Only text that decodes as a token is returned, but the tokens are **not** verified.
+/// Every token is only returned once, in the order it was found.
+///
+/// To bound the time spent on hostile text, at most 8 ways to join a wrapped token are decoded,
+/// and decoding stops once it took as long as decoding 4 tokens of the maximum length.
+/// Tokens after that are not returned. Likewise, only the parts of the first 16 multi-part tokens
+/// are collected, and at most 4096 parts in total.
+pub fn extract_tokens(text: &str) -> Vec<Token> {
+    let text = text
+        .chars()
+        .filter(|c| !INVISIBLE.contains(c))
+        .collect::<String>();
+    let lines = text.lines().map(words).collect::<Vec<_>>();
+    let limits = Limits::default();
+    let max_token_len = limits.max_token_len as u64;
+    let mut budget = max_token_len * max_token_len * MAX_DECODED_TOKENS;
+
+    let mut tokens = Vec::<Token>::new();
+    let mut token_parts = Vec::<TokenParts>::new();
+    let mut line_index = 0;
+    while line_index < lines.len() {
+        let line = &lines[line_index];
+        let mut next_line = line_index + 1;
+        for (word_index, word) in line.words.iter().enumerate() {
+            if !has_prefix(word) {
+                continue;
+            }
+            let run = if word_index == line.words.len() - 1 {
+                wrapped_run(word, &lines, line_index, &limits)
+            } else {
+                Run::single(word, line_index)
+            };
+
+            let Some((found, last_line)) =
+                run.candidates(&limits)
+                    .take(MAX_CANDIDATES)
+                    .find_map(|(candidate, last_line)| {
+                        Some((classify(candidate, &limits, &mut budget)?, last_line))
+                    })
+            else {
+                continue;
+            };
+            if last_line > line_index {
+                // The rest of a line that ended the token can contain more tokens.
+                next_line = if lines[last_line].words.len() > 1 {
+                    last_line
+                } else {
+                    last_line + 1
+                };
+            }
+            match found {
+                Found::Token(token) => push_unique(&mut tokens, token),
+                Found::Part(part) => insert_part(&mut token_parts, &part, &limits),
+            }
+        }
+        line_index = next_line;
+    }
+
+    for collected in token_parts {
+        if let Ok(token) = collected.assemble() {
+            push_unique(&mut tokens, token);
+        }
+    }
+    tokens
+}
+
+/// A word that starts a token, joined with all following lines it might have been wrapped onto.
+struct Run {
+    text: String,
+    /// The length of the text and the index of the line at the end of every line
+    ends: Vec<(usize, usize)>,
+}
+
+impl Run {
+    fn single(word: &str, line_index: usize) -> Self {
+        Self {
+            text: word.to_string(),
+            ends: vec![(word.len(), line_index)],
+        }
+    }
+
+    /// All texts the token might be, starting with the longest one,
+    /// without those that can not be a token of an acceptable size.
+    fn candidates<'a>(&'a self, limits: &Limits) -> impl Iterator<Item = (&'a str, usize)> {
+        // Every candidate is a prefix of the text, so the alphabet is only checked once.
+        let (max_len, max_body_len) = match split_token(&self.text) {
+            Ok((_, encoding, body)) => {
+                let prefix_len = self.text.len() - body.len();
+                let valid_len = body
+                    .bytes()
+                    .position(|c| !encoding.is_alphabet(c))
+                    .unwrap_or(body.len());
+                (
+                    prefix_len + valid_len,
+                    prefix_len + encoding.max_encoded_len(limits.max_compressed_size),
+                )
+            }
+            // Parts are only checked with their checksum, which takes linear time.
+            Err(_) => (self.text.len(), self.text.len()),
+        };
+        self.ends
+            .iter()
+            .rev()
+            .filter_map(move |&(end, line_index)| {
+                // Sentences can end right after a token.
+                let candidate = self.text[..end].trim_end_matches(['.', ':']);
+                (candidate.len() <= max_len.min(max_body_len)).then_some((candidate, line_index))
+            })
+    }
+}
+
+/// Joins the word with the first words of the following lines, as long as they could continue it.
+fn wrapped_run(word: &str, lines: &[Line<'_>], line_index: usize, limits: &Limits) -> Run {
+    let mut run = Run::single(word, line_index);
+    for (index, next) in lines.iter().enumerate().skip(line_index + 1) {
+        let Some(first) = next.continuation() else {
+            break;
+        };
+        if has_prefix(first) || run.text.len() + first.len() > limits.max_token_len {
+            break;
+        }
+        run.text.push_str(first);
+        run.ends.push((run.text.len(), index));
+        if next.words.len() > 1 {
+            break;
+        }
+    }
+    run
+}
+
+enum Found {
+    Token(Token),
+    Part(String),
+}
+
+struct Line<'a> {
+    words: Vec<&'a str>,
+    /// Whether the first word starts the line, not counting whitespace and quote markers
+    starts_with_word: bool,
+}
+
+impl<'a> Line<'a> {
+    /// The first word, if it could continue a token from the previous line.
+    fn continuation(&self) -> Option<&'a str> {
+        self.words
+            .first()
+            .copied()
+            .filter(|_| self.starts_with_word)
+    }
+}
+
+fn words(line: &str) -> Line<'_> {
+    let line = line.trim_start_matches(|c: char| c.is_whitespace() || c == '>');
+    Line {
+        words: line
+            .split(|c: char| !is_token_char(c))
+            .filter(|word| !word.is_empty())
+            .collect(),
+        starts_with_word: line.starts_with(is_token_char),
+    }
+}
+
+/// Whether the character can be part of a token or a part in any encoding.
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, ':' | '/' | '.' | '-' | '_')
+}
+
+fn has_prefix(word: &str) -> bool {
+    word.len() > PREFIX.len() + 1
+        && word[..PREFIX.len()].eq_ignore_ascii_case(PREFIX)
+        && word[PREFIX.len()..=PREFIX.len()]
+            .to_ascii_lowercase()
+            .parse::<PayloadKind>()
+            .is_ok()
+}
+
+/// Decodes the candidate, as long as the budget for decoding is not used up.
+fn classify(candidate: &str, limits: &Limits, budget: &mut u64) -> Option<Found> {
+    if let Ok(token) = candidate.parse::<Token>() {
+        let len = candidate.len() as u64;
+        *budget = budget.checked_sub(len * len)?;
+        return decode_envelope_values(candidate, limits)
+            .ok()
+            .map(|_| Found::Token(token));
+    }
+    parts::is_part(candidate, limits).then(|| Found::Part(candidate.to_string()))
+}
+
+fn push_unique(tokens: &mut Vec<Token>, token: Token) {
+    if !tokens.contains(&token) {
+        tokens.push(token);
+    }
+}
+
+/// Adds the part to the multi-part token it belongs to, as long as the caps are not reached.
+fn insert_part(token_parts: &mut Vec<TokenParts>, part: &str, limits: &Limits) {
+    if token_parts.iter().map(TokenParts::len).sum::<usize>() >= MAX_PARTS {
+        return;
+    }
+    for collected in token_parts.iter_mut() {
+        match collected.insert(part) {
+            Err(GxtError::PartMismatch) => continue,
+            _ => return,
+        }
+    }
+    if token_parts.len() >= MAX_PART_GROUPS {
+        return;
+    }
+    let mut collected = TokenParts::with_limits(*limits);
+    if collected.insert(part).is_ok() {
+        token_parts.push(collected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::Identity;
+
+    fn token() -> Token {
+        Identity::generate()
+            .message()
+            .payload(&"a message that is long enough to be wrapped ".repeat(8))
+            .sign()
+            .unwrap()
+    }
+
+    fn wrap(text: &str, width: usize) -> String {
+        text.as_bytes()
+            .chunks(width)
+            .map(|line| format!("> {}\n", std::str::from_utf8(line).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn joins_wrapped_tokens() {
+        let token = token();
+        let text = format!(
+            "Here you go:\n{}Thanks for trading.\n",
+            wrap(token.as_str(), 40)
+        );
+        assert_eq!(extract_tokens(&text), vec![token]);
+    }
+
+    #[test]
+    fn finds_tokens_ending_sentences() {
+        let first = token();
+        let second = token();
+        let text = format!("first: {first}. second: `{second}`:");
+        assert_eq!(extract_tokens(&text), vec![first, second]);
+    }
+
+    #[test]
+    fn hostile_paste_does_not_hang() {
+        let token = token();
+        let hostile = wrap(&format!("gxm:{}", "z".repeat(400 * 90)), 90);
+        let text = format!("{hostile}\n{hostile}\n{token}\n");
+        let start = Instant::now();
+        assert_eq!(extract_tokens(&text), vec![token]);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn parts_with_huge_totals_are_ignored() {
+        let token = token();
+        let hostile = "gxm1/18446744073709551615:00000000.abc.8c4ec46d";
+        let text = format!("{hostile}\n{token}\n{hostile}\n");
+        assert_eq!(extract_tokens(&text), vec![token]);
+    }
+
+    #[test]
+    fn parts_of_too_many_tokens_are_ignored() {
+        let tokens = (0..=MAX_PART_GROUPS).map(|_| token()).collect::<Vec<_>>();
+        let text = tokens
+            .iter()
+            .flat_map(|token| token.split_into_parts(200).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(extract_tokens(&text), tokens[..MAX_PART_GROUPS]);
+    }
+
+    #[test]
+    fn many_long_hostile_tokens_do_not_hang() {
+        let text = format!("gxm:{}\n", "z".repeat(80 * 1000)).repeat(64);
+        let start = Instant::now();
+        assert!(extract_tokens(&text).is_empty());
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,639 @@
+use std::{fmt, io::Read, str::FromStr};
+
+use bip39::{Language, Mnemonic};
+use ed25519_dalek::SigningKey;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_cbor::Value as CborValue;
+
+use crate::{
+    Bytes32, DetachedSignature, Envelope, GxtError, JsonValue, KeyAgreement, Limits,
+    MessageBuilder, PREFIX, PayloadKind, ReplayGuard, Revocation, RevocationReason, Revocations,
+    Rotation, Token, Validity, decrypt_envelope, derive_enc_from_signing, detached, from_value,
+    make, parse_hex, password, random, revocation, rotation, verify_message,
+};
+
+/// A parsed private key together with the encryption keys derived from it.
+///
+/// Parse it once (e.g. when the game starts) and reuse it for every message
+/// instead of passing the key token around as a string.
+#[derive(Clone)]
+pub struct Identity {
+    pub(crate) signing_key: SigningKey,
+    pub(crate) secret_key: Bytes32,
+    pub(crate) encryption_key: Bytes32,
+}
+
+impl Identity {
+    /// Generates a new random identity.
+    pub fn generate() -> Self {
+        Self::from_signing_key(SigningKey::from_bytes(&random::bytes()))
+    }
+
+    pub(crate) fn from_signing_key(signing_key: SigningKey) -> Self {
+        let (secret_key, encryption_key) = derive_enc_from_signing(&signing_key);
+        Self {
+            signing_key,
+            secret_key,
+            encryption_key,
+        }
+    }
+
+    /// Packages the private key into a `gxk:` token.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn to_token(&self) -> Result<Token, GxtError> {
+        let key_json = serde_json::to_value(&self.signing_key)?;
+        make(
+            &self.signing_key,
+            PayloadKind::Key,
+            serde_cbor::value::to_value(&key_json)?,
+            None,
+            Validity::default(),
+        )?
+        .parse()
+    }
+
+    /// Packages the private key into a `gxe:` token, which is encrypted with the given password.
+    ///
+    /// The password is stretched with Argon2id, so guessing it is expensive.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn to_token_with_password(&self, password: &str) -> Result<Token, GxtError> {
+        make(
+            &self.signing_key,
+            PayloadKind::EncryptedKey,
+            password::lock(&self.signing_key, password)?,
+            None,
+            Validity::default(),
+        )?
+        .parse()
+    }
+
+    /// Parses a `gxe:` token and decrypts the private key inside with the given password.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::BadPassword`] if the password is wrong.
+    /// - returns [`GxtError::PasswordCostTooHigh`] if the key was locked with too expensive parameters.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn unlock(key: &str, password: &str) -> Result<Self, GxtError> {
+        let token = key.parse::<Token>()?;
+        if token.kind() != PayloadKind::EncryptedKey {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::EncryptedKey,
+                got: token.kind(),
+            });
+        }
+        let envelope = token.verify::<CborValue>()?;
+        let verification_key = parse_hex::<32>(&envelope.verification_key)?;
+        Ok(Self::from_signing_key(password::unlock(
+            &envelope.payload,
+            &verification_key,
+            password,
+        )?))
+    }
+
+    /// Encodes the private key as a BIP39 mnemonic of 24 english words.
+    ///
+    /// The last word contains a checksum, so typos are detected when restoring the key.
+    pub fn to_recovery_phrase(&self) -> String {
+        Mnemonic::from_entropy_in(Language::English, &self.signing_key.to_bytes())
+            .expect("32 bytes are a valid entropy length")
+            .to_string()
+    }
+
+    /// Restores an identity from a recovery phrase created by [`Identity::to_recovery_phrase`].
+    ///
+    /// Case and whitespace between the words are ignored.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::BadRecoveryPhrase`] if the phrase contains unknown words or the checksum is wrong.
+    pub fn from_recovery_phrase(phrase: &str) -> Result<Self, GxtError> {
+        let mnemonic = Mnemonic::parse_in(Language::English, phrase.to_lowercase())
+            .map_err(|e| GxtError::BadRecoveryPhrase(e.to_string()))?;
+        let seed: [u8; 32] = mnemonic.to_entropy().try_into().map_err(|_| {
+            GxtError::BadRecoveryPhrase("a key phrase must have 24 words".to_string())
+        })?;
+        Ok(Self::from_signing_key(SigningKey::from_bytes(&seed)))
+    }
+
+    /// The hex encoded verification key of this identity.
+    pub fn verification_key(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// The hex encoded encryption key of this identity.
+    pub fn encryption_key(&self) -> String {
+        hex::encode(self.encryption_key)
+    }
+
+    /// Creates an ID card containing the necessary data for
+    /// the encrypted communication and some opaque meta data.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn make_id_card<M: Serialize>(&self, meta: M) -> Result<IdCard, GxtError> {
+        make(
+            &self.signing_key,
+            PayloadKind::Id,
+            serde_cbor::value::to_value(meta)?,
+            None,
+            Validity::default(),
+        )?
+        .parse()
+    }
+
+    /// Creates a rotation, which tells the contacts of this identity
+    /// that it was replaced by the successor.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Invalid`] if the successor is this identity.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn rotate_to(&self, successor: &Identity) -> Result<Rotation, GxtError> {
+        self.rotate_to_with_validity(successor, Validity::default())
+    }
+
+    /// Creates a rotation like [`Identity::rotate_to`], which records when it was created
+    /// and is only valid in the given time window.
+    ///
+    /// The creation time is available as [`Rotation::created_at`] and tells contacts
+    /// since when messages should be signed by the successor.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Invalid`] if the successor is this identity.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn rotate_to_with_validity(
+        &self,
+        successor: &Identity,
+        validity: Validity,
+    ) -> Result<Rotation, GxtError> {
+        make(
+            &self.signing_key,
+            PayloadKind::Rotation,
+            rotation::endorse(self, successor)?,
+            None,
+            validity,
+        )?
+        .parse()
+    }
+
+    /// Creates a revocation certificate, which permanently revokes this identity.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn revoke(&self, reason: RevocationReason) -> Result<Revocation, GxtError> {
+        make(
+            &self.signing_key,
+            PayloadKind::Revocation,
+            revocation::revocation_payload(reason),
+            None,
+            Validity::default(),
+        )?
+        .parse()
+    }
+
+    /// Starts a message from this identity, see [`MessageBuilder`].
+    pub fn message(&self) -> MessageBuilder<'_> {
+        MessageBuilder::new(self)
+    }
+
+    /// Create a **public** message, which is signed but not encrypted.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn make_public_message<P: Serialize>(
+        &self,
+        payload: &P,
+        parent: Option<String>,
+    ) -> Result<Token, GxtError> {
+        self.make_public_message_with_validity(payload, parent, Validity::default())
+    }
+
+    /// Create a **public** message, which is only valid in the given time window.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn make_public_message_with_validity<P: Serialize>(
+        &self,
+        payload: &P,
+        parent: Option<String>,
+        validity: Validity,
+    ) -> Result<Token, GxtError> {
+        self.message()
+            .payload(payload)
+            .maybe_parent(parent)
+            .validity(validity)
+            .sign()
+    }
+
+    /// Create a **detached** signature over the data.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn sign_detached(&self, data: &[u8]) -> Result<DetachedSignature, GxtError> {
+        self.sign_reader(data)
+    }
+
+    /// Create a **detached** signature over the data read from the reader,
+    /// without loading all of it into memory.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn sign_reader<R: Read>(&self, reader: R) -> Result<DetachedSignature, GxtError> {
+        make(
+            &self.signing_key,
+            PayloadKind::Signature,
+            detached::signature_payload(reader)?,
+            None,
+            Validity::default(),
+        )?
+        .parse()
+    }
+
+    /// Re-signs an own ID card of an older protocol version with the current version.
+    ///
+    /// ID cards that already use the current version are returned unchanged.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::WrongKey`] if the ID card belongs to a different key.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn upgrade_id_card(&self, id_card: &IdCard) -> Result<IdCard, GxtError> {
+        if id_card.token().kind() != PayloadKind::Id {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::Id,
+                got: id_card.token().kind(),
+            });
+        }
+        if id_card.verification_key() != self.verification_key() {
+            return Err(GxtError::WrongKey);
+        }
+        if id_card.envelope.is_current_version() {
+            return Ok(id_card.clone());
+        }
+        self.make_id_card(&id_card.envelope.payload)
+    }
+
+    /// Create an **encrypted** message for the owner of the ID card.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message<P: Serialize>(
+        &self,
+        id_card: &IdCard,
+        payload: &P,
+        parent: Option<String>,
+    ) -> Result<Token, GxtError> {
+        self.encrypt_message_with_validity(id_card, payload, parent, Validity::default())
+    }
+
+    /// Create an **encrypted** message for the owner of the ID card,
+    /// which is only valid in the given time window.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message_with_validity<P: Serialize>(
+        &self,
+        id_card: &IdCard,
+        payload: &P,
+        parent: Option<String>,
+        validity: Validity,
+    ) -> Result<Token, GxtError> {
+        self.encrypt_message_with_key_agreement(
+            id_card,
+            payload,
+            parent,
+            validity,
+            KeyAgreement::Static,
+        )
+    }
+
+    /// Create an **encrypted** message for the owner of the ID card,
+    /// using the given [`KeyAgreement`].
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message_with_key_agreement<P: Serialize>(
+        &self,
+        id_card: &IdCard,
+        payload: &P,
+        parent: Option<String>,
+        validity: Validity,
+        agreement: KeyAgreement,
+    ) -> Result<Token, GxtError> {
+        self.message()
+            .to(id_card)
+            .payload(payload)
+            .maybe_parent(parent)
+            .validity(validity)
+            .key_agreement(agreement)
+            .encrypt()
+    }
+
+    /// Create an **encrypted** message for the owners of all ID cards.
+    ///
+    /// The payload is only encrypted once with a random key,
+    /// which is then encrypted for every recipient.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message_for_many<P: Serialize>(
+        &self,
+        id_cards: &[IdCard],
+        payload: &P,
+        parent: Option<String>,
+    ) -> Result<Token, GxtError> {
+        self.encrypt_message_for_many_with_validity(id_cards, payload, parent, Validity::default())
+    }
+
+    /// Create an **encrypted** message for the owners of all ID cards,
+    /// which is only valid in the given time window.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message_for_many_with_validity<P: Serialize>(
+        &self,
+        id_cards: &[IdCard],
+        payload: &P,
+        parent: Option<String>,
+        validity: Validity,
+    ) -> Result<Token, GxtError> {
+        self.encrypt_message_for_many_with_key_agreement(
+            id_cards,
+            payload,
+            parent,
+            validity,
+            KeyAgreement::Static,
+        )
+    }
+
+    /// Create an **encrypted** message for the owners of all ID cards,
+    /// using the given [`KeyAgreement`].
+    ///
+    /// # Errors
+    /// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn encrypt_message_for_many_with_key_agreement<P: Serialize>(
+        &self,
+        id_cards: &[IdCard],
+        payload: &P,
+        parent: Option<String>,
+        validity: Validity,
+        agreement: KeyAgreement,
+    ) -> Result<Token, GxtError> {
+        self.message()
+            .to_all(id_cards)
+            .payload(payload)
+            .maybe_parent(parent)
+            .validity(validity)
+            .key_agreement(agreement)
+            .encrypt()
+    }
+
+    /// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`].
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn decrypt_message<P: DeserializeOwned>(
+        &self,
+        message: &Token,
+    ) -> Result<Envelope<P>, GxtError> {
+        decrypt_envelope(message.verify::<CborValue>()?, self, &Limits::default())
+    }
+
+    /// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+    /// rejecting it if it is expired or not valid yet at the given unix timestamp.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Expired`] if the message is expired.
+    /// - returns [`GxtError::NotYetValid`] if the message was created more than
+    ///   [`crate::ALLOWED_CLOCK_SKEW`] seconds after `now`.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn decrypt_message_at<P: DeserializeOwned>(
+        &self,
+        message: &Token,
+        now: u64,
+    ) -> Result<Envelope<P>, GxtError> {
+        decrypt_envelope(
+            message.verify_at::<CborValue>(now)?,
+            self,
+            &Limits::default(),
+        )
+    }
+
+    /// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+    /// using custom size limits instead of the default ones.
+    ///
+    /// The decompressed size limit applies to the decrypted payload as well as to the token.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn decrypt_message_with_limits<P: DeserializeOwned>(
+        &self,
+        message: &Token,
+        limits: &Limits,
+    ) -> Result<Envelope<P>, GxtError> {
+        decrypt_envelope(
+            message.verify_with_limits::<CborValue>(limits)?,
+            self,
+            limits,
+        )
+    }
+
+    /// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+    /// rejecting it if it was signed by a revoked key.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Revoked`] if the key was revoked.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn decrypt_message_with_revocations<P: DeserializeOwned>(
+        &self,
+        message: &Token,
+        revocations: &Revocations,
+    ) -> Result<Envelope<P>, GxtError> {
+        decrypt_envelope(
+            message.verify_with_revocations::<CborValue>(revocations)?,
+            self,
+            &Limits::default(),
+        )
+    }
+
+    /// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+    /// but only the first time this message is redeemed with the given [`ReplayGuard`].
+    ///
+    /// The message is only recorded as redeemed after it was successfully decrypted.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Replayed`] if the message was already redeemed.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn decrypt_message_once<P: DeserializeOwned, G: ReplayGuard + ?Sized>(
+        &self,
+        message: &Token,
+        guard: &mut G,
+    ) -> Result<Envelope<P>, GxtError> {
+        let envelope = message.verify::<CborValue>()?;
+        if guard.contains(&envelope.id)? {
+            return Err(GxtError::Replayed);
+        }
+        let envelope = decrypt_envelope::<P>(envelope, self, &Limits::default())?;
+        if !guard.insert(&envelope.id)? {
+            return Err(GxtError::Replayed);
+        }
+        Ok(envelope)
+    }
+}
+
+impl FromStr for Identity {
+    type Err = GxtError;
+
+    /// Parses either a `gxk:` token or a hex encoded private key.
+    ///
+    /// Password protected `gxe:` tokens have to be parsed with [`Identity::unlock`] instead.
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        let key = key.trim();
+        let signing_key = if key
+            .get(..PREFIX.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(PREFIX))
+        {
+            let token = verify_message::<JsonValue>(key)?;
+            if token.kind == PayloadKind::EncryptedKey {
+                return Err(GxtError::PasswordRequired);
+            }
+            if token.kind != PayloadKind::Key {
+                return Err(GxtError::UnexpectedKind {
+                    expected: PayloadKind::Key,
+                    got: token.kind,
+                });
+            }
+            from_value(token.payload)?
+        } else {
+            SigningKey::from_bytes(&parse_hex::<32>(key)?)
+        };
+        Ok(Self::from_signing_key(signing_key))
+    }
+}
+
+impl fmt::Debug for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Identity")
+            .field("verification_key", &self.verification_key())
+            .field("encryption_key", &self.encryption_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A verified ID card of a peer.
+///
+/// The signature is only checked once when parsing, so the card can be stored
+/// and used to encrypt any number of messages for its owner.
+///
+/// A public message can be parsed as ID card of its author as well, so replies to it
+/// can be encrypted for them. Its payload is then returned as meta data.
+#[derive(Clone, Debug)]
+pub struct IdCard {
+    token: Token,
+    envelope: Envelope<CborValue>,
+    pub(crate) encryption_key: Bytes32,
+}
+
+impl IdCard {
+    /// The `gxi:` token of this ID card, or the `gxp:` token of a public message.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// The id of this ID card.
+    pub fn id(&self) -> &str {
+        &self.envelope.id
+    }
+
+    /// The hex encoded verification key of the owner.
+    pub fn verification_key(&self) -> &str {
+        &self.envelope.verification_key
+    }
+
+    /// The hex encoded encryption key of the owner.
+    pub fn encryption_key(&self) -> &str {
+        &self.envelope.encryption_key
+    }
+
+    /// The protocol version of this ID card.
+    pub fn version(&self) -> u8 {
+        self.envelope.version
+    }
+
+    /// Deserializes the meta data of the ID card.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn meta<M: DeserializeOwned>(&self) -> Result<M, GxtError> {
+        Ok(serde_cbor::value::from_value(
+            self.envelope.payload.clone(),
+        )?)
+    }
+}
+
+impl FromStr for IdCard {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Token>()?.try_into()
+    }
+}
+
+impl TryFrom<Token> for IdCard {
+    type Error = GxtError;
+
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        if !matches!(token.kind(), PayloadKind::Id | PayloadKind::Public) {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::Id,
+                got: token.kind(),
+            });
+        }
+        let envelope = token.verify::<CborValue>()?;
+        let encryption_key = parse_hex::<32>(&envelope.encryption_key)?;
+        Ok(Self {
+            token,
+            envelope,
+            encryption_key,
+        })
+    }
+}
+
+impl fmt::Display for IdCard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.token.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decrypt_with_limits_applies_to_the_plaintext() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let payload = "a".repeat(16 * 1024);
+        let token = alice
+            .message()
+            .payload(&payload)
+            .to(&bob.make_id_card("bob").unwrap())
+            .compress_plaintext(true)
+            .encrypt()
+            .unwrap();
+        let limits = Limits {
+            max_decompressed_size: 4 * 1024,
+            ..Limits::default()
+        };
+        assert!(matches!(
+            bob.decrypt_message_with_limits::<String>(&token, &limits),
+            Err(GxtError::DecompressedTooLarge { limit: 4096 })
+        ));
+        let envelope = bob.decrypt_message::<String>(&token).unwrap();
+        assert_eq!(envelope.payload, payload);
+    }
+}
@@ -15,6 +15,7 @@
 //! You should also store the ID of a trade so that it can be verified that a message was in response to
 //! that ID. When responding to a message, make sure you set the parent field to the ID of the message you're
 //! responding to. Otherwise there is no way to verify the message chain.
+//! A [`Conversation`] can be used to check such a chain, including forks and missing messages.
 //!
 //! You might also want to store some lightweight meta data for the trade request. For instance the
 //! identifiers of the items that were taken away. This way they can be given back when the other player
@@ -25,13 +26,11 @@
 #![deny(missing_docs)]
 #![allow(clippy::similar_names)]
 
-use std::{fmt, str::FromStr};
+use std::{fmt, io::Read, str::FromStr, time::Duration};
 
-use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
 use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
 use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
-use rand::RngCore;
-use rand::rngs::OsRng;
 use serde::Deserialize;
 use serde::{Serialize, de::DeserializeOwned};
 use serde_cbor::Value as CborValue;
@@ -76,9 +75,53 @@ pub fn from_json<T: DeserializeOwned>(s: &str) -> Result<T, serde_json::Error> {
 /// but if you have a use-case with more than one language, you need to keep this in mind.
 pub mod advisory;
 
+/// Deterministic random numbers to create known-answer test vectors, see `gxt/examples/test_vectors.rs`.
+///
+/// Only available with the `test-vectors` feature, which must **never** be enabled in a game.
+#[cfg(feature = "test-vectors")]
+pub mod test_vectors;
+
+mod batch;
+mod builder;
+mod canonical;
+mod conversation;
+mod detached;
+mod dictionary;
+mod encoding;
+mod extract;
+mod identity;
+mod parts;
+mod password;
+mod random;
+mod replay;
+mod revocation;
+mod rotation;
+mod version;
+
+pub use batch::{verify_many, verify_many_with_limits};
+pub use builder::MessageBuilder;
+pub use conversation::{Conversation, ConversationIssue};
+pub use detached::DetachedSignature;
+pub use dictionary::{
+    Dictionary, compression_dictionary, register_dictionary, set_compression_dictionary,
+};
+pub use encoding::Encoding;
+use encoding::split_token;
+pub use extract::extract_tokens;
+pub use identity::{IdCard, Identity};
+pub use parts::TokenParts;
+pub use replay::{FileReplayGuard, MemoryReplayGuard, ReplayGuard};
+pub use revocation::{Revocation, RevocationReason, Revocations};
+pub use rotation::{KeyRotations, Rotation, RotationIssue};
+pub use version::ProtocolVersion;
+
 const PREFIX: &str = "gx";
 const SIGNATURE_DOMAIN: &[u8] = b"GXT";
-const VERSION: u8 = 4;
+
+/// Seconds that the clock of the creator of a message may be ahead of ours.
+///
+/// Messages whose `created_at` is further in the future are rejected when verified at a given time.
+pub const ALLOWED_CLOCK_SKEW: u64 = 5 * 60;
 
 type Bytes32 = [u8; 32];
 type Bytes64 = [u8; 64];
@@ -93,9 +136,52 @@ pub enum GxtError {
     #[error("decode error: {0}")]
     /// Base58 decoding failed
     Decode(#[from] bs58::decode::Error),
+    #[error("decode error: {0}")]
+    /// Base32 or Base64 decoding failed
+    BadEncoding(#[from] data_encoding::DecodeError),
+    /// The checksum of a Bech32 token does not match, usually because of a typo
+    #[error("bad checksum")]
+    BadChecksum,
+    /// The name of the encoding is unknown
+    #[error("unknown encoding")]
+    UnknownEncoding,
+    /// Not all parts of a multi-part token are there
+    #[error("missing parts {missing:?} of {total}")]
+    MissingParts {
+        /// The numbers of the missing parts, starting at 1
+        missing: Vec<usize>,
+        /// The number of parts of the token
+        total: usize,
+    },
+    /// A part belongs to a different token than the other parts
+    #[error("part belongs to a different token")]
+    PartMismatch,
+    /// The parts would have to be shorter than their header
+    #[error("part length limit {limit} is too small")]
+    PartLimitTooSmall {
+        /// The maximum length of a part
+        limit: usize,
+    },
+    /// A part claims that the token has more parts than a token within the limits can be split into
+    #[error("too many parts. limit is {limit} got {got}")]
+    TooManyParts {
+        /// The maximum number of parts
+        limit: usize,
+        /// The number of parts the part claims
+        got: usize,
+    },
     /// Compression or decompression failed
     #[error("decompress error: {0}")]
     Compression(#[from] std::io::Error),
+    /// The token was compressed with a dictionary that was not registered
+    #[error("unknown dictionary {0:#010x}")]
+    UnknownDictionary(u32),
+    /// The data is not a zstd dictionary, or its id is reserved
+    #[error("invalid dictionary")]
+    InvalidDictionary,
+    /// A different dictionary with the same id is already registered
+    #[error("a different dictionary with id {0:#010x} is already registered")]
+    DictionaryConflict(u32),
     /// Encryption or decryption failed
     #[error("encrypt error: {0}")]
     Encryption(String),
@@ -131,10 +217,92 @@ pub enum GxtError {
     /// Received an unknown payload kind
     #[error("unknown payload kind")]
     UnknownPayloadKind,
+    /// The message is past its expiry date
+    #[error("message expired")]
+    Expired,
+    /// The message claims to be created later than now, beyond the allowed clock skew
+    #[error("message is not valid yet")]
+    NotYetValid,
+    /// The message was already redeemed
+    #[error("message was already redeemed")]
+    Replayed,
+    /// Reading or writing the replay store failed
+    #[error("replay store error: {0}")]
+    ReplayStore(std::io::Error),
+    /// The token string is longer than allowed
+    #[error("token too long. limit is {limit} got {got}")]
+    TokenTooLong {
+        /// The maximum length
+        limit: usize,
+        /// The length we got
+        got: usize,
+    },
+    /// The compressed data is larger than allowed
+    #[error("compressed data too large. limit is {limit} got {got}")]
+    CompressedTooLarge {
+        /// The maximum size
+        limit: usize,
+        /// The size we got
+        got: usize,
+    },
+    /// The decompressed data is larger than allowed
+    #[error("decompressed data too large. limit is {limit}")]
+    DecompressedTooLarge {
+        /// The maximum size
+        limit: usize,
+    },
+    /// A message needs at least one recipient
+    #[error("no recipients")]
+    NoRecipients,
+    /// A public message can not have recipients
+    #[error("public messages have no recipients")]
+    UnexpectedRecipients,
+    /// The token is of a different kind than required
+    #[error("expected {expected} token, got {got}")]
+    UnexpectedKind {
+        /// The kind that was required
+        expected: PayloadKind,
+        /// The kind of the token
+        got: PayloadKind,
+    },
+    /// The key is protected by a password and has to be unlocked first
+    #[error("key is protected by a password")]
+    PasswordRequired,
+    /// The password does not unlock the key
+    #[error("wrong password")]
+    BadPassword,
+    /// The password of the key is hashed with a higher cost than we are willing to compute
+    #[error("password hashing too expensive. limit for {parameter} is {limit} got {got}")]
+    PasswordCostTooHigh {
+        /// The name of the parameter, `m`, `t` or `p`
+        parameter: &'static str,
+        /// The maximum value
+        limit: u32,
+        /// The value we got
+        got: u64,
+    },
+    /// The recovery phrase contains unknown words or a wrong checksum
+    #[error("invalid recovery phrase: {0}")]
+    BadRecoveryPhrase(String),
+    /// The data does not match the detached signature
+    #[error("data does not match the signature")]
+    HashMismatch,
+    /// The token uses a protocol version that can not be decoded
+    #[error("unsupported version {0}")]
+    UnsupportedVersion(u8),
+    /// The token was signed by a different key
+    #[error("token was signed by a different key")]
+    WrongKey,
+    /// The key that signed the message was revoked
+    #[error("key was revoked ({reason})")]
+    Revoked {
+        /// Why the key was revoked
+        reason: RevocationReason,
+    },
 }
 
 /// What kind of payload was sent
-#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum PayloadKind {
     /// ID card
     Id,
@@ -142,6 +310,16 @@ pub enum PayloadKind {
     Msg,
     /// A key packaged into a gxt token
     Key,
+    /// A key packaged into a gxt token, encrypted with a password
+    EncryptedKey,
+    /// A statement that a key was replaced by a successor key
+    Rotation,
+    /// A certificate that permanently revokes a key
+    Revocation,
+    /// A signed message, which anyone can read
+    Public,
+    /// A signature over data that is not part of the token
+    Signature,
 }
 
 impl FromStr for PayloadKind {
@@ -152,6 +330,11 @@ impl FromStr for PayloadKind {
             "i" => Ok(PayloadKind::Id),
             "m" => Ok(PayloadKind::Msg),
             "k" => Ok(PayloadKind::Key),
+            "e" => Ok(PayloadKind::EncryptedKey),
+            "r" => Ok(PayloadKind::Rotation),
+            "v" => Ok(PayloadKind::Revocation),
+            "p" => Ok(PayloadKind::Public),
+            "s" => Ok(PayloadKind::Signature),
             _ => Err(GxtError::UnknownPayloadKind),
         }
     }
@@ -163,6 +346,224 @@ impl fmt::Display for PayloadKind {
             Self::Id => write!(f, "id"),
             Self::Msg => write!(f, "msg"),
             Self::Key => write!(f, "key"),
+            Self::EncryptedKey => write!(f, "encrypted key"),
+            Self::Rotation => write!(f, "rotation"),
+            Self::Revocation => write!(f, "revocation"),
+            Self::Public => write!(f, "public"),
+            Self::Signature => write!(f, "signature"),
+        }
+    }
+}
+
+/// A GXT token string with a known payload kind.
+///
+/// Parsing only checks the prefix. Use [`Token::verify`] to check the signature.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Token {
+    kind: PayloadKind,
+    encoding: Encoding,
+    token: String,
+}
+
+impl Token {
+    /// The payload kind of this token, taken from its prefix.
+    pub fn kind(&self) -> PayloadKind {
+        self.kind
+    }
+
+    /// The encoding of this token, taken from its prefix.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Writes the same token with a different encoding.
+    ///
+    /// The token is **not** verified and keeps its id and signature.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::TokenTooLong`] if the token gets longer than the default limit.
+    /// - returns a corresponding [`GxtError`], if the token can not be decoded.
+    pub fn to_encoding(&self, encoding: Encoding) -> Result<Token, GxtError> {
+        if encoding == self.encoding {
+            return Ok(self.clone());
+        }
+        let limits = Limits::default();
+        let (kind, compressed_message) = decode_compressed(&self.token, &limits)?;
+        Ok(Self {
+            kind,
+            encoding,
+            token: encode_compressed(kind, encoding, &compressed_message, &limits)?,
+        })
+    }
+
+    /// The token as string.
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// Splits the token into numbered parts, which are at most `max_part_len` characters long,
+    /// for chats that limit the length of a message. Use [`TokenParts`] to join them again.
+    ///
+    /// A token that already fits is returned as a single part.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::PartLimitTooSmall`] if `max_part_len` leaves no room for data.
+    pub fn split_into_parts(&self, max_part_len: usize) -> Result<Vec<String>, GxtError> {
+        parts::split(self, max_part_len)
+    }
+
+    /// Verify the signature of the token and return a parsed [`Envelope`].
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn verify<P: DeserializeOwned>(&self) -> Result<Envelope<P>, GxtError> {
+        verify_message(&self.token)
+    }
+
+    /// Verify the signature of the token and return a parsed [`Envelope`],
+    /// rejecting it if it is expired or not valid yet at the given unix timestamp.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Expired`] if the message is expired.
+    /// - returns [`GxtError::NotYetValid`] if the message was created more than
+    ///   [`ALLOWED_CLOCK_SKEW`] seconds after `now`.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn verify_at<P: DeserializeOwned>(&self, now: u64) -> Result<Envelope<P>, GxtError> {
+        verify_message_at(&self.token, now)
+    }
+
+    /// Verify the signature of the token and return a parsed [`Envelope`],
+    /// using custom size limits instead of the default ones.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn verify_with_limits<P: DeserializeOwned>(
+        &self,
+        limits: &Limits,
+    ) -> Result<Envelope<P>, GxtError> {
+        verify_message_with_limits(&self.token, limits)
+    }
+
+    /// Verify the signature of the token and return a parsed [`Envelope`],
+    /// rejecting it if it was signed by a revoked key.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::Revoked`] if the key was revoked.
+    /// - returns a corresponding [`GxtError`], depending on what went wrong.
+    pub fn verify_with_revocations<P: DeserializeOwned>(
+        &self,
+        revocations: &Revocations,
+    ) -> Result<Envelope<P>, GxtError> {
+        verify_message_with_revocations(&self.token, revocations)
+    }
+}
+
+impl FromStr for Token {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let token = s.trim();
+        let (kind, encoding, _) = split_token(token)?;
+        Ok(Self {
+            kind,
+            encoding,
+            token: token.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.token)
+    }
+}
+
+/// Optional time window in which a message is valid.
+///
+/// Both values are unix timestamps in seconds and are covered by the signature.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Validity {
+    /// When the message was created
+    pub created_at: Option<u64>,
+    /// From this point in time on, the message is no longer valid
+    pub expires_at: Option<u64>,
+}
+
+impl Validity {
+    /// Creates a validity window that starts at `now` and ends after `lifetime`.
+    pub fn expires_in(now: u64, lifetime: Duration) -> Self {
+        Self {
+            created_at: Some(now),
+            expires_at: Some(now.saturating_add(lifetime.as_secs())),
+        }
+    }
+}
+
+/// How the sender and the recipient of an encrypted message agree on its key.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum KeyAgreement {
+    /// Diffie-Hellman between the keys of the sender and the recipient.
+    ///
+    /// Leaking either key exposes every message ever exchanged between them.
+    #[default]
+    Static,
+    /// Diffie-Hellman between a new key, which is only used for this message,
+    /// and the key of the recipient.
+    ///
+    /// The secret part of the ephemeral key is thrown away after encrypting,
+    /// so leaking the key of the sender does not expose the message.
+    /// Leaking the key of the recipient still does.
+    Ephemeral,
+    /// Like [`KeyAgreement::Ephemeral`], but the keys of the sender and the recipient
+    /// are mixed in as well, so the ciphertext can only be decrypted in a message
+    /// of the original sender.
+    EphemeralStatic,
+}
+
+impl KeyAgreement {
+    /// The value of the `kdf` field for this key agreement.
+    fn kdf(self) -> Option<&'static str> {
+        match self {
+            KeyAgreement::Static => None,
+            KeyAgreement::Ephemeral => Some("x25519-es"),
+            KeyAgreement::EphemeralStatic => Some("x25519-es-ss"),
+        }
+    }
+
+    fn from_kdf(kdf: Option<&str>) -> Result<Self, GxtError> {
+        match kdf {
+            None => Ok(KeyAgreement::Static),
+            Some("x25519-es") => Ok(KeyAgreement::Ephemeral),
+            Some("x25519-es-ss") => Ok(KeyAgreement::EphemeralStatic),
+            Some(_) => Err(GxtError::Invalid),
+        }
+    }
+}
+
+/// Size limits that are enforced when encoding and decoding tokens.
+///
+/// These protect games from hostile tokens, e.g. a small token that decompresses
+/// into gigabytes of data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum length of the token string, including the prefix
+    ///
+    /// Independent of this limit, the data of a token must not be longer than
+    /// `max_compressed_size` bytes can be encoded into, which is checked before decoding it.
+    pub max_token_len: usize,
+    /// Maximum size of the compressed data, before encoding it as text
+    pub max_compressed_size: usize,
+    /// Maximum size of the decompressed CBOR data
+    pub max_decompressed_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            // enough for `max_compressed_size` bytes in Base58, which takes about 1.37 characters per byte
+            max_token_len: 88 * 1024,
+            max_compressed_size: 64 * 1024,
+            max_decompressed_size: 1024 * 1024,
         }
     }
 }
@@ -171,6 +572,10 @@ impl fmt::Display for PayloadKind {
 /// Parsed, verified GXT record.
 ///
 /// Represents a decoded token after signature verification and/or decryption.
+///
+/// Keys, ids and signatures are always hex encoded. The same applies to the binary fields
+/// of payloads created by the protocol itself, like encrypted messages or rotations,
+/// even if the token stores them as byte strings.
 #[serde(bound(serialize = "P: Serialize", deserialize = "P: Deserialize<'de>"))]
 pub struct Envelope<P> {
     /// Version
@@ -185,12 +590,75 @@ pub struct Envelope<P> {
     pub payload: P,
     /// Id of the Parent Message
     pub parent: Option<String>,
+    /// Unix timestamp of when the message was created
+    pub created_at: Option<u64>,
+    /// Unix timestamp from which on the message is no longer valid
+    pub expires_at: Option<u64>,
     /// Id of this Message
     pub id: String,
     /// Signature of this Message
     pub signature: String,
 }
 
+impl<P> Envelope<P> {
+    /// Whether the parent is covered by the id and signature of this message.
+    ///
+    /// Tokens up to version 4 did not sign the parent, so anyone relaying such a token
+    /// could have changed which message it claims to answer.
+    pub fn is_parent_authenticated(&self) -> bool {
+        ProtocolVersion::get(self.version).is_ok_and(|version| version.authenticates_parent)
+    }
+
+    /// Whether the ciphertext of an encrypted message is bound to the header of this message.
+    ///
+    /// Tokens up to version 6 did not authenticate the header as associated data,
+    /// so their ciphertext could be moved into a different envelope.
+    pub fn is_header_authenticated(&self) -> bool {
+        ProtocolVersion::get(self.version).is_ok_and(|version| version.authenticates_header)
+    }
+
+    /// Whether the token was created with the current protocol version.
+    pub fn is_current_version(&self) -> bool {
+        ProtocolVersion::get(self.version).is_ok_and(|version| version.is_current())
+    }
+
+    /// Whether the message is expired at the given unix timestamp.
+    pub fn is_expired_at(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+
+    /// Whether the message claims to be created after the given unix timestamp,
+    /// by more than [`ALLOWED_CLOCK_SKEW`].
+    pub fn is_not_yet_valid_at(&self, now: u64) -> bool {
+        self.created_at
+            .is_some_and(|created_at| created_at > now.saturating_add(ALLOWED_CLOCK_SKEW))
+    }
+
+    fn check_validity(self, now: u64) -> Result<Self, GxtError> {
+        if self.is_expired_at(now) {
+            return Err(GxtError::Expired);
+        }
+        if self.is_not_yet_valid_at(now) {
+            return Err(GxtError::NotYetValid);
+        }
+        Ok(self)
+    }
+
+    /// Revocations themselves are never rejected, so they can be passed on
+    /// even after the key was revoked.
+    fn check_revocation(self, revocations: &Revocations) -> Result<Self, GxtError> {
+        if self.kind == PayloadKind::Revocation {
+            return Ok(self);
+        }
+        match revocations.get(&self.verification_key) {
+            Some(revocation) => Err(GxtError::Revoked {
+                reason: revocation.reason(),
+            }),
+            None => Ok(self),
+        }
+    }
+}
+
 impl<P: Serialize + DeserializeOwned> fmt::Display for Envelope<P> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "valid           : true")?;
@@ -200,9 +668,25 @@ impl<P: Serialize + DeserializeOwned> fmt::Display for Envelope<P> {
             "parent          : {}",
             self.parent.as_ref().map_or_else(
                 || "-".to_string(),
-                |parent| format!("{} ({})", parent, &parent[..8])
+                |parent| if self.is_parent_authenticated() {
+                    format!("{} ({})", parent, &parent[..8])
+                } else {
+                    format!("{} ({}, unauthenticated)", parent, &parent[..8])
+                }
             )
         )?;
+        writeln!(
+            f,
+            "created at      : {}",
+            self.created_at
+                .map_or_else(|| "-".to_string(), |t| t.to_string())
+        )?;
+        writeln!(
+            f,
+            "expires at      : {}",
+            self.expires_at
+                .map_or_else(|| "-".to_string(), |t| t.to_string())
+        )?;
         writeln!(f, "id              : {} ({})", self.id, &self.id[..8])?;
         writeln!(
             f,
@@ -237,15 +721,130 @@ pub enum KeyKind {
 
 /// Creates a private key for a peer.
 pub fn make_key() -> String {
-    let key = SigningKey::generate(&mut OsRng);
-    let key_json = serde_json::to_value(&key).expect("Should never happen.");
-    make(
-        &key,
-        PayloadKind::Key,
-        serde_cbor::value::to_value(&key_json).expect("Should never happen."),
-        None,
-    )
-    .expect("Should never happen.")
+    Identity::generate()
+        .to_token()
+        .expect("Should never happen.")
+        .to_string()
+}
+
+/// Creates a private key for a peer, which is encrypted with the given password.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_key_with_password(password: &str) -> Result<String, GxtError> {
+    Ok(Identity::generate()
+        .to_token_with_password(password)?
+        .to_string())
+}
+
+/// Encrypts an existing private key with the given password.
+///
+/// # Errors
+/// - returns [`GxtError::PasswordRequired`] if the key is already encrypted.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn lock_key(key: &str, password: &str) -> Result<String, GxtError> {
+    Ok(key
+        .parse::<Identity>()?
+        .to_token_with_password(password)?
+        .to_string())
+}
+
+/// Decrypts a password protected private key and returns it as plain `gxk:` token.
+///
+/// # Errors
+/// - returns [`GxtError::BadPassword`] if the password is wrong.
+/// - returns [`GxtError::PasswordCostTooHigh`] if the key was locked with too expensive parameters.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn unlock_key(key: &str, password: &str) -> Result<String, GxtError> {
+    Ok(Identity::unlock(key, password)?.to_token()?.to_string())
+}
+
+/// Re-encrypts a password protected private key with a new password.
+///
+/// # Errors
+/// - returns [`GxtError::BadPassword`] if the old password is wrong.
+/// - returns [`GxtError::PasswordCostTooHigh`] if the key was locked with too expensive parameters.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn change_key_password(
+    key: &str,
+    old_password: &str,
+    new_password: &str,
+) -> Result<String, GxtError> {
+    Ok(Identity::unlock(key, old_password)?
+        .to_token_with_password(new_password)?
+        .to_string())
+}
+
+/// Exports a private key as a recovery phrase of 24 english words,
+/// which can be written down and turned back into the key with [`key_from_recovery_phrase`].
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn key_to_recovery_phrase(key: &str) -> Result<String, GxtError> {
+    Ok(key.parse::<Identity>()?.to_recovery_phrase())
+}
+
+/// Restores a private key from a recovery phrase and returns it as `gxk:` token.
+///
+/// # Errors
+/// - returns [`GxtError::BadRecoveryPhrase`] if the phrase contains unknown words or the checksum is wrong.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn key_from_recovery_phrase(phrase: &str) -> Result<String, GxtError> {
+    Ok(Identity::from_recovery_phrase(phrase)?
+        .to_token()?
+        .to_string())
+}
+
+/// Creates a rotation token, which tells the contacts of the owner of `key`
+/// that it was replaced by `successor_key`.
+///
+/// Both keys are needed, because the successor has to accept the endorsement.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_rotation(key: &str, successor_key: &str) -> Result<String, GxtError> {
+    make_rotation_with_validity(key, successor_key, Validity::default())
+}
+
+/// Creates a rotation token like [`make_rotation`], which records when it was created
+/// and is only valid in the given time window.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_rotation_with_validity(
+    key: &str,
+    successor_key: &str,
+    validity: Validity,
+) -> Result<String, GxtError> {
+    Ok(key
+        .parse::<Identity>()?
+        .rotate_to_with_validity(&successor_key.parse()?, validity)?
+        .to_string())
+}
+
+/// Creates a revocation certificate, which permanently revokes the key.
+///
+/// The certificate can be created in advance and stored offline until it is needed.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_revocation(key: &str, reason: RevocationReason) -> Result<String, GxtError> {
+    Ok(key.parse::<Identity>()?.revoke(reason)?.to_string())
+}
+
+/// Re-signs an ID card of an older protocol version with the current version.
+///
+/// Only the owner of the ID card can do this, because the new card has to be signed again.
+/// The meta data stays the same.
+///
+/// # Errors
+/// - returns [`GxtError::WrongKey`] if the ID card belongs to a different key.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn upgrade_id_card(key: &str, id_card: &str) -> Result<String, GxtError> {
+    Ok(key
+        .parse::<Identity>()?
+        .upgrade_id_card(&id_card.parse()?)?
+        .to_string())
 }
 
 /// Creates an ID card containing the necessary data for
@@ -257,162 +856,733 @@ pub fn make_id_card<M: Serialize + DeserializeOwned>(
     key: &str,
     meta: M,
 ) -> Result<String, GxtError> {
-    let key = parse_key(key.trim())?;
-    make(
-        &key,
-        PayloadKind::Id,
-        serde_cbor::value::to_value(meta)?,
-        None,
-    )
+    Ok(key.parse::<Identity>()?.make_id_card(meta)?.to_string())
 }
 
 /// Verify the signature of a message and return a parsed [`Envelope`].
 ///
 /// # Errors
 /// - returns a corresponding [`GxtError`], depending on what went wrong.
-pub fn verify_message<P: Serialize + DeserializeOwned>(msg: &str) -> Result<Envelope<P>, GxtError> {
-    let msg = msg.trim();
-    let (kind, msg) = get_kind(msg)?;
-    let raw = decode_message(msg)?;
-    let envelope_cbor: CborValue = serde_cbor::from_slice(&raw)?;
+pub fn verify_message<P: DeserializeOwned>(msg: &str) -> Result<Envelope<P>, GxtError> {
+    verify_message_with_limits(msg, &Limits::default())
+}
 
-    let arr = match envelope_cbor {
-        CborValue::Array(a) if a.len() == 7 => a,
-        _ => return Err(GxtError::Invalid),
-    };
+/// Verify the signature of a message and return a parsed [`Envelope`],
+/// using custom size limits instead of the default ones.
+///
+/// # Errors
+/// - returns [`GxtError::TokenTooLong`], [`GxtError::CompressedTooLarge`] or
+///   [`GxtError::DecompressedTooLarge`] if the message exceeds the limits.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn verify_message_with_limits<P: DeserializeOwned>(
+    msg: &str,
+    limits: &Limits,
+) -> Result<Envelope<P>, GxtError> {
+    let signed = SignedEnvelope::decode(msg, limits)?;
+    signed.verify_signature()?;
+    signed.into_envelope()
+}
+
+/// A decoded token whose id was checked, but not its signature.
+struct SignedEnvelope {
+    kind: PayloadKind,
+    version: ProtocolVersion,
+    verification_key: VerifyingKey,
+    encryption_key: Bytes32,
+    payload: CborValue,
+    parent: Option<Bytes32>,
+    validity: Validity,
+    id: Bytes32,
+    signature: Signature,
+    /// The data that was signed
+    preimage: Vec<u8>,
+}
 
-    let mut values = arr.into_iter();
+impl SignedEnvelope {
+    fn decode(msg: &str, limits: &Limits) -> Result<Self, GxtError> {
+        let (kind, values) = decode_envelope_values(msg, limits)?;
+        let mut values = values.into_iter();
 
-    let version = match values.next() {
-        Some(CborValue::Integer(i)) if i == VERSION.into() => VERSION,
-        _ => return Err(GxtError::Invalid),
-    };
-    let verification_key_bytes = match values.next() {
-        Some(CborValue::Text(t)) => parse_hex::<32>(&t)?,
-        _ => return Err(GxtError::Invalid),
-    };
-    let encryption_key = match values.next() {
-        Some(CborValue::Text(t)) => parse_hex::<32>(&t)?,
-        _ => return Err(GxtError::Invalid),
-    };
-    let payload = match values.next() {
-        Some(payload) => payload.clone(),
-        _ => return Err(GxtError::Invalid),
-    };
-    let parent = match values.next() {
-        Some(CborValue::Text(t)) if !t.is_empty() => Some(parse_hex::<32>(&t)?),
-        Some(CborValue::Text(_)) => None,
-        _ => return Err(GxtError::Invalid),
-    };
-    let id = match values.next() {
-        Some(CborValue::Text(t)) => parse_hex::<32>(&t)?,
-        _ => return Err(GxtError::Invalid),
-    };
-    let signature_bytes = match values.next() {
-        Some(CborValue::Text(t)) => parse_hex::<64>(&t)?,
-        _ => return Err(GxtError::Invalid),
-    };
+        let version = ProtocolVersion::get(parse_version(values.next())?)?;
+        if values.len() != version.field_count() {
+            return Err(GxtError::Invalid);
+        }
+        let verification_key_bytes = parse_field::<32>(version, values.next())?;
+        let encryption_key = parse_field::<32>(version, values.next())?;
+        let payload = match values.next() {
+            Some(payload) => payload.clone(),
+            _ => return Err(GxtError::Invalid),
+        };
+        let parent = version.parse_bytes(values.next())?;
+        let parent = if parent.is_empty() {
+            None
+        } else {
+            Some(to_array::<32>(parent)?)
+        };
+        let validity = if version.has_validity {
+            Validity {
+                created_at: parse_timestamp(values.next())?,
+                expires_at: parse_timestamp(values.next())?,
+            }
+        } else {
+            Validity::default()
+        };
+        let id = parse_field::<32>(version, values.next())?;
+        let signature_bytes = parse_field::<64>(version, values.next())?;
+
+        let canonical = get_canonical_representation(
+            version,
+            &verification_key_bytes,
+            &encryption_key,
+            payload.clone(),
+            parent,
+            validity,
+        )?;
+        let expect = blake3::hash(&canonical);
+        if id != *expect.as_bytes() {
+            return Err(GxtError::BadId);
+        }
 
-    let canonical =
-        get_canonical_representation(&verification_key_bytes, &encryption_key, payload.clone())?;
-    let expect = blake3::hash(&canonical);
-    if id != *expect.as_bytes() {
-        return Err(GxtError::BadId);
+        let verification_key =
+            VerifyingKey::from_bytes(&verification_key_bytes).map_err(|_| GxtError::Invalid)?;
+        Ok(Self {
+            kind,
+            version,
+            verification_key,
+            encryption_key,
+            payload,
+            parent,
+            validity,
+            id,
+            signature: Signature::from_bytes(&signature_bytes),
+            preimage: preimage(&canonical),
+        })
     }
 
-    let verification_key =
-        VerifyingKey::from_bytes(&verification_key_bytes).map_err(|_| GxtError::Invalid)?;
-    let signature = Signature::from_bytes(&signature_bytes);
-    verification_key
-        .verify_strict(&preimage(&canonical), &signature)
-        .map_err(|_| GxtError::BadSig)?;
+    fn verify_signature(&self) -> Result<(), GxtError> {
+        self.verification_key
+            .verify_strict(&self.preimage, &self.signature)
+            .map_err(|_| GxtError::BadSig)
+    }
 
-    Ok(Envelope {
-        version,
-        verification_key: hex::encode(verification_key_bytes),
-        encryption_key: hex::encode(encryption_key),
-        parent: parent.map(hex::encode),
-        kind,
-        payload: serde_cbor::value::from_value(payload)?,
-        id: hex::encode(id),
-        signature: hex::encode(signature_bytes),
-    })
+    fn into_envelope<P: DeserializeOwned>(self) -> Result<Envelope<P>, GxtError> {
+        let payload = if has_protocol_payload(self.kind) {
+            hex_fields(self.payload)
+        } else {
+            self.payload
+        };
+        Ok(Envelope {
+            version: self.version.version,
+            verification_key: hex::encode(self.verification_key.to_bytes()),
+            encryption_key: hex::encode(self.encryption_key),
+            parent: self.parent.map(hex::encode),
+            created_at: self.validity.created_at,
+            expires_at: self.validity.expires_at,
+            kind: self.kind,
+            payload: serde_cbor::value::from_value(payload)?,
+            id: hex::encode(self.id),
+            signature: hex::encode(self.signature.to_bytes()),
+        })
+    }
 }
 
-/// Create an **encrypted** message for the owner of the
-/// ID card that was passed in.
+/// Writes the token with a different [`Encoding`], without verifying or signing it again.
 ///
 /// # Errors
 /// - returns a corresponding [`GxtError`], depending on what went wrong.
-pub fn encrypt_message<P: Serialize + DeserializeOwned>(
-    key: &str,
-    id_card: &str,
-    payload: &P,
-    parent: Option<String>,
-) -> Result<String, GxtError> {
-    let id_card = verify_message::<CborValue>(id_card.trim())?;
-    let their_encryption_key = parse_hex::<32>(&id_card.encryption_key)?;
-    let key = parse_key(key.trim())?;
-    let (my_secret_key, _my_encryption_key) = derive_enc_from_signing(&key);
-    let encryption_key = enc_derive_key_from_pairs(&my_secret_key, &their_encryption_key);
-    let cipher = XChaCha20Poly1305::new(&encryption_key);
-    let mut nonce_bytes = [0u8; 24];
-    OsRng.fill_bytes(&mut nonce_bytes);
-    let nonce = XNonce::from_slice(&nonce_bytes);
-    let plaintext = serde_cbor::to_vec(&payload)?;
-    let cipher_text = cipher
-        .encrypt(nonce, plaintext.as_ref())
-        .map_err(|e| GxtError::Encryption(e.to_string()))?;
+pub fn convert_token(token: &str, encoding: Encoding) -> Result<String, GxtError> {
+    Ok(token.parse::<Token>()?.to_encoding(encoding)?.to_string())
+}
 
-    let mut message = std::collections::BTreeMap::new();
-    message.insert(
-        CborValue::Text("to".into()),
-        CborValue::Text(hex::encode(their_encryption_key)),
-    );
-    let mut encrypted_message = std::collections::BTreeMap::new();
-    encrypted_message.insert(
-        CborValue::Text("alg".into()),
-        CborValue::Text("xchacha20poly1305".into()),
-    );
-    encrypted_message.insert(
-        CborValue::Text("n24".into()),
-        CborValue::Text(hex::encode(nonce_bytes)),
-    );
-    encrypted_message.insert(
-        CborValue::Text("ct".into()),
-        CborValue::Text(hex::encode(&cipher_text)),
-    );
-    message.insert(
-        CborValue::Text("enc".into()),
-        CborValue::Map(encrypted_message),
-    );
-    let payload = CborValue::Map(message);
-    make(
-        &key,
-        PayloadKind::Msg,
-        payload,
-        parent.map(|parent| parse_hex::<32>(&parent)).transpose()?,
-    )
+/// Splits a token into numbered parts, which are at most `max_part_len` characters long.
+///
+/// # Errors
+/// - returns [`GxtError::PartLimitTooSmall`] if `max_part_len` leaves no room for data.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn split_into_parts(token: &str, max_part_len: usize) -> Result<Vec<String>, GxtError> {
+    token.parse::<Token>()?.split_into_parts(max_part_len)
 }
 
-/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`].
+/// Joins the parts of a token, which can be given in any order, **without** verifying it.
 ///
 /// # Errors
+/// - returns [`GxtError::MissingParts`] if not all parts are there.
 /// - returns a corresponding [`GxtError`], depending on what went wrong.
-pub fn decrypt_message<P: Serialize + DeserializeOwned>(
-    message: &str,
-    key: &str,
+pub fn join_parts<S: AsRef<str>>(parts: &[S]) -> Result<String, GxtError> {
+    let mut token_parts = TokenParts::new();
+    for part in parts {
+        token_parts.insert(part.as_ref())?;
+    }
+    Ok(token_parts.assemble()?.to_string())
+}
+
+/// Reads the protocol version of a token **without** verifying it.
+///
+/// Unlike [`verify_message`], this also works for versions that can no longer be decoded.
+/// Use [`ProtocolVersion::get`] to find out what the version supports.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn token_version(msg: &str) -> Result<u8, GxtError> {
+    let (_, values) = decode_envelope_values(msg, &Limits::default())?;
+    parse_version(values.into_iter().next())
+}
+
+/// Verify the signature of a message and return a parsed [`Envelope`],
+/// rejecting it if it is expired or not valid yet at the given unix timestamp.
+///
+/// # Errors
+/// - returns [`GxtError::Expired`] if the message is expired.
+/// - returns [`GxtError::NotYetValid`] if the message was created more than
+///   [`ALLOWED_CLOCK_SKEW`] seconds after `now`.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn verify_message_at<P: DeserializeOwned>(
+    msg: &str,
+    now: u64,
 ) -> Result<Envelope<P>, GxtError> {
-    let mut envelope = verify_message::<CborValue>(message.trim())?;
+    verify_message(msg)?.check_validity(now)
+}
 
-    let key = parse_key(key)?;
+/// Verify the signature of a message and return a parsed [`Envelope`],
+/// rejecting it if it was signed by a revoked key.
+///
+/// # Errors
+/// - returns [`GxtError::Revoked`] if the key was revoked.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn verify_message_with_revocations<P: DeserializeOwned>(
+    msg: &str,
+    revocations: &Revocations,
+) -> Result<Envelope<P>, GxtError> {
+    verify_message(msg)?.check_revocation(revocations)
+}
+
+/// Create a **public** message, which is signed but not encrypted,
+/// so anyone can verify and read it.
+///
+/// Use it for announcements or trade offers that are posted publicly.
+/// Replies should be encrypted messages for the author, with the id of the public message as parent.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_public_message<P: Serialize + DeserializeOwned>(
+    key: &str,
+    payload: &P,
+    parent: Option<String>,
+) -> Result<String, GxtError> {
+    make_public_message_with_validity(key, payload, parent, Validity::default())
+}
+
+/// Create a **public** message, which is only valid in the given time window.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn make_public_message_with_validity<P: Serialize + DeserializeOwned>(
+    key: &str,
+    payload: &P,
+    parent: Option<String>,
+    validity: Validity,
+) -> Result<String, GxtError> {
+    Ok(key
+        .parse::<Identity>()?
+        .make_public_message_with_validity(payload, parent, validity)?
+        .to_string())
+}
+
+/// Create a **detached** signature over the data, e.g. the content of a save file.
+///
+/// The token only contains the hash of the data, so it stays small no matter how large the data is.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn sign_detached(key: &str, data: &[u8]) -> Result<String, GxtError> {
+    Ok(key.parse::<Identity>()?.sign_detached(data)?.to_string())
+}
+
+/// Verify a **detached** signature and check that it was created over the data.
+/// Returns the parsed [`Envelope`] of the signature, which identifies the signer.
+///
+/// # Errors
+/// - returns [`GxtError::HashMismatch`] if the data does not match the signature.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn verify_detached<P: DeserializeOwned>(
+    signature: &str,
+    data: &[u8],
+) -> Result<Envelope<P>, GxtError> {
+    let signature = signature.parse::<DetachedSignature>()?;
+    signature.verify(data)?;
+    signature.token().verify()
+}
+
+/// Create an **encrypted** message for the owner of the
+/// ID card that was passed in.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_card: &str,
+    payload: &P,
+    parent: Option<String>,
+) -> Result<String, GxtError> {
+    encrypt_message_with_validity(key, id_card, payload, parent, Validity::default())
+}
+
+/// Create an **encrypted** message for the owner of the
+/// ID card that was passed in, which is only valid in the given time window.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message_with_validity<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_card: &str,
+    payload: &P,
+    parent: Option<String>,
+    validity: Validity,
+) -> Result<String, GxtError> {
+    let identity = key.parse::<Identity>()?;
+    let id_card = id_card.parse::<IdCard>()?;
+    Ok(identity
+        .encrypt_message_with_validity(&id_card, payload, parent, validity)?
+        .to_string())
+}
+
+/// Create an **encrypted** message for the owner of the
+/// ID card that was passed in, using the given [`KeyAgreement`].
+///
+/// Use [`KeyAgreement::Ephemeral`] for messages that should stay secret,
+/// even if the key of the sender leaks later on.
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message_with_key_agreement<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_card: &str,
+    payload: &P,
+    parent: Option<String>,
+    validity: Validity,
+    agreement: KeyAgreement,
+) -> Result<String, GxtError> {
+    let identity = key.parse::<Identity>()?;
+    let id_card = id_card.parse::<IdCard>()?;
+    Ok(identity
+        .encrypt_message_with_key_agreement(&id_card, payload, parent, validity, agreement)?
+        .to_string())
+}
+
+/// Create an **encrypted** message for the owners of all ID cards that were passed in.
+///
+/// The payload is only encrypted once, so the token stays small even for many recipients.
+///
+/// # Errors
+/// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message_for_many<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_cards: &[&str],
+    payload: &P,
+    parent: Option<String>,
+) -> Result<String, GxtError> {
+    encrypt_message_for_many_with_validity(key, id_cards, payload, parent, Validity::default())
+}
+
+/// Create an **encrypted** message for the owners of all ID cards that were passed in,
+/// which is only valid in the given time window.
+///
+/// # Errors
+/// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message_for_many_with_validity<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_cards: &[&str],
+    payload: &P,
+    parent: Option<String>,
+    validity: Validity,
+) -> Result<String, GxtError> {
+    let identity = key.parse::<Identity>()?;
+    let id_cards = id_cards
+        .iter()
+        .map(|id_card| id_card.parse::<IdCard>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(identity
+        .encrypt_message_for_many_with_validity(&id_cards, payload, parent, validity)?
+        .to_string())
+}
+
+/// Create an **encrypted** message for the owners of all ID cards that were passed in,
+/// using the given [`KeyAgreement`].
+///
+/// # Errors
+/// - returns [`GxtError::NoRecipients`] if no ID card was passed in.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn encrypt_message_for_many_with_key_agreement<P: Serialize + DeserializeOwned>(
+    key: &str,
+    id_cards: &[&str],
+    payload: &P,
+    parent: Option<String>,
+    validity: Validity,
+    agreement: KeyAgreement,
+) -> Result<String, GxtError> {
+    let identity = key.parse::<Identity>()?;
+    let id_cards = id_cards
+        .iter()
+        .map(|id_card| id_card.parse::<IdCard>())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(identity
+        .encrypt_message_for_many_with_key_agreement(
+            &id_cards, payload, parent, validity, agreement,
+        )?
+        .to_string())
+}
+
+/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`].
+///
+/// # Errors
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn decrypt_message<P: Serialize + DeserializeOwned>(
+    message: &str,
+    key: &str,
+) -> Result<Envelope<P>, GxtError> {
+    key.parse::<Identity>()?.decrypt_message(&message.parse()?)
+}
+
+/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+/// rejecting it if it is expired or not valid yet at the given unix timestamp.
+///
+/// # Errors
+/// - returns [`GxtError::Expired`] if the message is expired.
+/// - returns [`GxtError::NotYetValid`] if the message was created more than
+///   [`ALLOWED_CLOCK_SKEW`] seconds after `now`.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn decrypt_message_at<P: Serialize + DeserializeOwned>(
+    message: &str,
+    key: &str,
+    now: u64,
+) -> Result<Envelope<P>, GxtError> {
+    key.parse::<Identity>()?
+        .decrypt_message_at(&message.parse()?, now)
+}
+
+/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+/// using custom size limits instead of the default ones.
+///
+/// # Errors
+/// - returns [`GxtError::TokenTooLong`], [`GxtError::CompressedTooLarge`] or
+///   [`GxtError::DecompressedTooLarge`] if the message exceeds the limits.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn decrypt_message_with_limits<P: Serialize + DeserializeOwned>(
+    message: &str,
+    key: &str,
+    limits: &Limits,
+) -> Result<Envelope<P>, GxtError> {
+    key.parse::<Identity>()?
+        .decrypt_message_with_limits(&message.parse()?, limits)
+}
+
+/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+/// rejecting it if it was signed by a revoked key.
+///
+/// # Errors
+/// - returns [`GxtError::Revoked`] if the key was revoked.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn decrypt_message_with_revocations<P: Serialize + DeserializeOwned>(
+    message: &str,
+    key: &str,
+    revocations: &Revocations,
+) -> Result<Envelope<P>, GxtError> {
+    key.parse::<Identity>()?
+        .decrypt_message_with_revocations(&message.parse()?, revocations)
+}
+
+/// Verify the signature of a message, decrypt its payload and return a parsed [`Envelope`],
+/// but only the first time this message is redeemed with the given [`ReplayGuard`].
+///
+/// # Errors
+/// - returns [`GxtError::Replayed`] if the message was already redeemed.
+/// - returns a corresponding [`GxtError`], depending on what went wrong.
+pub fn decrypt_message_once<P: Serialize + DeserializeOwned, G: ReplayGuard + ?Sized>(
+    message: &str,
+    key: &str,
+    guard: &mut G,
+) -> Result<Envelope<P>, GxtError> {
+    key.parse::<Identity>()?
+        .decrypt_message_once(&message.parse()?, guard)
+}
+
+fn encrypt_payload<P: Serialize + ?Sized>(
+    identity: &Identity,
+    their_encryption_key: &Bytes32,
+    payload: &P,
+    parent: Option<Bytes32>,
+    validity: Validity,
+    agreement: KeyAgreement,
+    options: &TokenOptions,
+) -> Result<String, GxtError> {
+    let ephemeral_key = generate_ephemeral_key(agreement);
+    let encryption_key = derive_message_key(
+        KeyPurpose::Payload,
+        agreement,
+        (&identity.secret_key, their_encryption_key),
+        ephemeral_key
+            .as_ref()
+            .map(|(secret_key, _)| (secret_key, their_encryption_key)),
+    )?;
+    let version = ProtocolVersion::for_new_tokens();
+    let aad = associated_data(
+        version,
+        &identity.signing_key.verifying_key().to_bytes(),
+        &identity.encryption_key,
+        std::slice::from_ref(their_encryption_key),
+        parent,
+        validity,
+    )?;
+    let (plaintext, compressed) = compress_plaintext(serde_cbor::to_vec(&payload)?, options)?;
+    let (nonce_bytes, cipher_text) = seal(&encryption_key, &plaintext, &aad)?;
+
+    let mut message = std::collections::BTreeMap::new();
+    message.insert(
+        CborValue::Text("to".into()),
+        version.bytes_value(their_encryption_key),
+    );
+    message.insert(
+        CborValue::Text("enc".into()),
+        encrypted_value(
+            version,
+            &nonce_bytes,
+            &cipher_text,
+            compressed,
+            agreement,
+            ephemeral_key.as_ref().map(|(_, public_key)| public_key),
+        ),
+    );
+    let payload = CborValue::Map(message);
+    make_with_options(
+        &identity.signing_key,
+        PayloadKind::Msg,
+        payload,
+        parent,
+        validity,
+        options,
+    )
+}
+
+fn encrypt_payload_for_many<P: Serialize + ?Sized>(
+    identity: &Identity,
+    their_encryption_keys: &[Bytes32],
+    payload: &P,
+    parent: Option<Bytes32>,
+    validity: Validity,
+    agreement: KeyAgreement,
+    options: &TokenOptions,
+) -> Result<String, GxtError> {
+    if their_encryption_keys.is_empty() {
+        return Err(GxtError::NoRecipients);
+    }
+    let mut seen = std::collections::HashSet::new();
+    let their_encryption_keys = their_encryption_keys
+        .iter()
+        .copied()
+        .filter(|their_encryption_key| seen.insert(*their_encryption_key))
+        .collect::<Vec<_>>();
+    let version = ProtocolVersion::for_new_tokens();
+    let aad = associated_data(
+        version,
+        &identity.signing_key.verifying_key().to_bytes(),
+        &identity.encryption_key,
+        &their_encryption_keys,
+        parent,
+        validity,
+    )?;
+
+    let ephemeral_key = generate_ephemeral_key(agreement);
+    let content_key = random::bytes::<32>();
+    let (plaintext, compressed) = compress_plaintext(serde_cbor::to_vec(&payload)?, options)?;
+    let (nonce_bytes, cipher_text) = seal(Key::from_slice(&content_key), &plaintext, &aad)?;
+
+    let mut recipients = Vec::with_capacity(their_encryption_keys.len());
+    for their_encryption_key in &their_encryption_keys {
+        let wrapping_key = derive_message_key(
+            KeyPurpose::ContentKey,
+            agreement,
+            (&identity.secret_key, their_encryption_key),
+            ephemeral_key
+                .as_ref()
+                .map(|(secret_key, _)| (secret_key, their_encryption_key)),
+        )?;
+        let (key_nonce, wrapped_key) = seal(&wrapping_key, &content_key, &aad)?;
+        let mut recipient = std::collections::BTreeMap::new();
+        recipient.insert(
+            CborValue::Text("key".into()),
+            version.bytes_value(their_encryption_key),
+        );
+        recipient.insert(
+            CborValue::Text("n24".into()),
+            version.bytes_value(&key_nonce),
+        );
+        recipient.insert(
+            CborValue::Text("ck".into()),
+            version.bytes_value(&wrapped_key),
+        );
+        recipients.push(CborValue::Map(recipient));
+    }
+
+    let mut message = std::collections::BTreeMap::new();
+    message.insert(CborValue::Text("to".into()), CborValue::Array(recipients));
+    message.insert(
+        CborValue::Text("enc".into()),
+        encrypted_value(
+            version,
+            &nonce_bytes,
+            &cipher_text,
+            compressed,
+            agreement,
+            ephemeral_key.as_ref().map(|(_, public_key)| public_key),
+        ),
+    );
+    let payload = CborValue::Map(message);
+    make_with_options(
+        &identity.signing_key,
+        PayloadKind::Msg,
+        payload,
+        parent,
+        validity,
+        options,
+    )
+}
+
+fn encrypted_value(
+    version: ProtocolVersion,
+    nonce: &[u8; 24],
+    cipher_text: &[u8],
+    compressed: bool,
+    agreement: KeyAgreement,
+    ephemeral_key: Option<&Bytes32>,
+) -> CborValue {
+    let mut encrypted_message = std::collections::BTreeMap::new();
+    encrypted_message.insert(
+        CborValue::Text("alg".into()),
+        CborValue::Text("xchacha20poly1305".into()),
+    );
+    if let Some(kdf) = agreement.kdf() {
+        encrypted_message.insert(CborValue::Text("kdf".into()), CborValue::Text(kdf.into()));
+    }
+    if let Some(ephemeral_key) = ephemeral_key {
+        encrypted_message.insert(
+            CborValue::Text("epk".into()),
+            version.bytes_value(ephemeral_key),
+        );
+    }
+    if compressed {
+        encrypted_message.insert(
+            CborValue::Text("cmp".into()),
+            CborValue::Text("zstd".into()),
+        );
+    }
+    encrypted_message.insert(CborValue::Text("n24".into()), version.bytes_value(nonce));
+    encrypted_message.insert(
+        CborValue::Text("ct".into()),
+        version.bytes_value(cipher_text),
+    );
+    CborValue::Map(encrypted_message)
+}
+
+/// Compresses the plaintext of an encrypted message with the dictionary of the token,
+/// if the sender opted into it and that makes it smaller.
+///
+/// Ciphertext can not be compressed, so without this encrypted messages would not benefit
+/// from the dictionary at all. It is off by default, because the length of compressed
+/// plaintext tells how similar the payload is to the dictionary (see `spec.md`).
+/// Without a dictionary, the plaintext is left as is, because zstd alone barely shrinks short payloads.
+fn compress_plaintext(
+    plaintext: Vec<u8>,
+    options: &TokenOptions,
+) -> Result<(Vec<u8>, bool), GxtError> {
+    let Some(dictionary) = options
+        .dictionary
+        .as_ref()
+        .filter(|_| options.compress_plaintext)
+    else {
+        return Ok((plaintext, false));
+    };
+    let compressed = dictionary::compress(&plaintext, Some(dictionary))?;
+    if compressed.len() < plaintext.len() {
+        Ok((compressed, true))
+    } else {
+        Ok((plaintext, false))
+    }
+}
+
+/// Serializes the header fields of an encrypted message, which are passed to the AEAD
+/// as associated data, so the ciphertext can not be moved into a different envelope.
+///
+/// Messages up to version 6 did not use associated data.
+fn associated_data(
+    version: ProtocolVersion,
+    verification_key: &Bytes32,
+    encryption_key: &Bytes32,
+    their_encryption_keys: &[Bytes32],
+    parent: Option<Bytes32>,
+    validity: Validity,
+) -> Result<Vec<u8>, GxtError> {
+    if !version.authenticates_header {
+        return Ok(Vec::new());
+    }
+    let header = CborValue::Array(vec![
+        CborValue::Integer(version.version.into()),
+        version.bytes_value(verification_key),
+        version.bytes_value(encryption_key),
+        CborValue::Array(
+            their_encryption_keys
+                .iter()
+                .map(|key| version.bytes_value(key))
+                .collect(),
+        ),
+        version.bytes_value(&parent.map(Vec::from).unwrap_or_default()),
+        timestamp_value(validity.created_at),
+        timestamp_value(validity.expires_at),
+    ]);
+    Ok(preimage(&canonical::to_vec(&header)?))
+}
+
+fn seal(key: &Key, plaintext: &[u8], aad: &[u8]) -> Result<([u8; 24], Vec<u8>), GxtError> {
+    let cipher = XChaCha20Poly1305::new(key);
+    let nonce_bytes = random::bytes::<24>();
+    let nonce = XNonce::from_slice(&nonce_bytes);
+    let cipher_text = cipher
+        .encrypt(
+            nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|e| GxtError::Encryption(e.to_string()))?;
+    Ok((nonce_bytes, cipher_text))
+}
+
+fn open(key: &Key, nonce: &[u8; 24], cipher_text: &[u8], aad: &[u8]) -> Result<Vec<u8>, GxtError> {
+    let cipher = XChaCha20Poly1305::new(key);
+    let nonce = XNonce::from_slice(nonce);
+    cipher
+        .decrypt(
+            nonce,
+            Payload {
+                msg: cipher_text,
+                aad,
+            },
+        )
+        .map_err(|e| GxtError::Encryption(e.to_string()))
+}
+
+/// Decrypts the payload of a verified envelope,
+/// decompressing the plaintext with the same limit as the token itself.
+fn decrypt_envelope<P: DeserializeOwned>(
+    envelope: Envelope<CborValue>,
+    identity: &Identity,
+    limits: &Limits,
+) -> Result<Envelope<P>, GxtError> {
     let CborValue::Map(map) = &envelope.payload else {
         return Err(GxtError::Invalid);
     };
-    let to = match map.get(&CborValue::Text("to".into())) {
-        Some(CborValue::Text(t)) => parse_hex::<32>(t)?,
-        _ => return Err(GxtError::Invalid),
-    };
     let Some(CborValue::Map(encm)) = map.get(&CborValue::Text("enc".into())) else {
         return Err(GxtError::Invalid);
     };
@@ -424,59 +1594,192 @@ pub fn decrypt_message<P: Serialize + DeserializeOwned>(
         Some(CborValue::Text(t)) => hex::decode(t)?,
         _ => return Err(GxtError::Invalid),
     };
+    let agreement = match encm.get(&CborValue::Text("kdf".into())) {
+        Some(CborValue::Text(t)) => KeyAgreement::from_kdf(Some(t))?,
+        None => KeyAgreement::Static,
+        _ => return Err(GxtError::Invalid),
+    };
+    let ephemeral_key = match encm.get(&CborValue::Text("epk".into())) {
+        Some(CborValue::Text(t)) => Some(parse_hex::<32>(t)?),
+        None => None,
+        _ => return Err(GxtError::Invalid),
+    };
+    let compressed = match encm.get(&CborValue::Text("cmp".into())) {
+        Some(CborValue::Text(t)) if t == "zstd" => true,
+        None => false,
+        _ => return Err(GxtError::Invalid),
+    };
+    let version = ProtocolVersion::get(envelope.version)?;
+    let sender_verification_key = parse_hex::<32>(&envelope.verification_key)?;
+    let sender_encryption_key = parse_hex::<32>(&envelope.encryption_key)?;
+    let parent = envelope
+        .parent
+        .as_deref()
+        .map(parse_hex::<32>)
+        .transpose()?;
+    let validity = Validity {
+        created_at: envelope.created_at,
+        expires_at: envelope.expires_at,
+    };
+    let header_associated_data = |their_encryption_keys: &[Bytes32]| {
+        associated_data(
+            version,
+            &sender_verification_key,
+            &sender_encryption_key,
+            their_encryption_keys,
+            parent,
+            validity,
+        )
+    };
+    let static_keys = (&identity.secret_key, &sender_encryption_key);
+    let ephemeral_keys = ephemeral_key
+        .as_ref()
+        .map(|ephemeral_key| (&identity.secret_key, ephemeral_key));
+
+    let (key, aad) = match map.get(&CborValue::Text("to".into())) {
+        Some(CborValue::Text(t)) => {
+            if parse_hex::<32>(t)? != identity.encryption_key {
+                return Err(GxtError::AccessDenied);
+            }
+            let key =
+                derive_message_key(KeyPurpose::Payload, agreement, static_keys, ephemeral_keys)?;
+            (key, header_associated_data(&[identity.encryption_key])?)
+        }
+        Some(CborValue::Array(recipients)) => {
+            let wrapping_key = derive_message_key(
+                KeyPurpose::ContentKey,
+                agreement,
+                static_keys,
+                ephemeral_keys,
+            )?;
+            let aad = header_associated_data(&recipient_keys(recipients)?)?;
+            (
+                unwrap_content_key(recipients, identity, &wrapping_key, &aad)?,
+                aad,
+            )
+        }
+        _ => return Err(GxtError::Invalid),
+    };
 
-    let (my_secret_key, my_encryption_key) = derive_enc_from_signing(&key);
-    if to != my_encryption_key {
-        return Err(GxtError::AccessDenied);
+    let mut plaintext = open(&key, &nonce, &cipher_text, &aad)?;
+    if compressed {
+        plaintext = decompress(&plaintext, limits.max_decompressed_size)?;
     }
-
-    let key = enc_derive_key_from_pairs(&my_secret_key, &parse_hex(&envelope.encryption_key)?);
-    let cipher = XChaCha20Poly1305::new(&key);
-    let nonce = XNonce::from_slice(&nonce);
-    let plaintext = cipher
-        .decrypt(nonce, cipher_text.as_ref())
-        .map_err(|e| GxtError::Encryption(e.to_string()))?;
-    envelope.payload = serde_cbor::from_slice(&plaintext)?;
+    let payload: CborValue = serde_cbor::from_slice(&plaintext)?;
 
     Ok(Envelope {
         version: envelope.version,
         verification_key: envelope.verification_key,
         encryption_key: envelope.encryption_key,
         kind: envelope.kind,
-        payload: serde_cbor::value::from_value(envelope.payload)?,
+        payload: serde_cbor::value::from_value(payload)?,
         parent: envelope.parent,
+        created_at: envelope.created_at,
+        expires_at: envelope.expires_at,
         id: envelope.id,
         signature: envelope.signature,
     })
 }
 
+/// The keys of all recipient entries, in the order they appear in the message.
+fn recipient_keys(recipients: &[CborValue]) -> Result<Vec<Bytes32>, GxtError> {
+    recipients
+        .iter()
+        .map(|recipient| match recipient {
+            CborValue::Map(recipient) => match recipient.get(&CborValue::Text("key".into())) {
+                Some(CborValue::Text(t)) => parse_hex::<32>(t),
+                _ => Err(GxtError::Invalid),
+            },
+            _ => Err(GxtError::Invalid),
+        })
+        .collect()
+}
+
+/// Finds the recipient entry for the given identity and decrypts the content key stored in it.
+fn unwrap_content_key(
+    recipients: &[CborValue],
+    identity: &Identity,
+    wrapping_key: &Key,
+    aad: &[u8],
+) -> Result<Key, GxtError> {
+    for recipient in recipients {
+        let CborValue::Map(recipient) = recipient else {
+            return Err(GxtError::Invalid);
+        };
+        let key = match recipient.get(&CborValue::Text("key".into())) {
+            Some(CborValue::Text(t)) => parse_hex::<32>(t)?,
+            _ => return Err(GxtError::Invalid),
+        };
+        if key != identity.encryption_key {
+            continue;
+        }
+        let nonce = match recipient.get(&CborValue::Text("n24".into())) {
+            Some(CborValue::Text(t)) => parse_hex::<24>(t)?,
+            _ => return Err(GxtError::Invalid),
+        };
+        let wrapped_key = match recipient.get(&CborValue::Text("ck".into())) {
+            Some(CborValue::Text(t)) => hex::decode(t)?,
+            _ => return Err(GxtError::Invalid),
+        };
+        let content_key = open(wrapping_key, &nonce, &wrapped_key, aad)?;
+        if content_key.len() != 32 {
+            return Err(GxtError::Invalid);
+        }
+        return Ok(Key::from_slice(&content_key).to_owned());
+    }
+    Err(GxtError::AccessDenied)
+}
+
 #[allow(clippy::too_many_arguments)]
 fn cbor_array(
+    version: ProtocolVersion,
     verification_key: &Bytes32,
     encryption_key: &Bytes32,
     payload: CborValue,
     parent: Option<Bytes32>,
+    validity: Validity,
     id: Option<&Bytes32>,
     signature: Option<&Bytes64>,
 ) -> Result<Vec<u8>, GxtError> {
-    let envelope_values = CborValue::Array(vec![
-        CborValue::Integer(VERSION.into()),
-        CborValue::Text(hex::encode(verification_key)),
-        CborValue::Text(hex::encode(encryption_key)),
+    let mut envelope_values = vec![
+        CborValue::Integer(version.version.into()),
+        version.bytes_value(verification_key),
+        version.bytes_value(encryption_key),
         payload,
-        CborValue::Text(parent.map(hex::encode).unwrap_or_default()),
-        CborValue::Text(id.map(hex::encode).unwrap_or_default()),
-        CborValue::Text(signature.map(hex::encode).unwrap_or_default()),
-    ]);
-    Ok(serde_cbor::to_vec(&envelope_values)?)
+        version.bytes_value(&parent.map(Vec::from).unwrap_or_default()),
+    ];
+    if version.has_validity {
+        envelope_values.push(timestamp_value(validity.created_at));
+        envelope_values.push(timestamp_value(validity.expires_at));
+    }
+    envelope_values.push(version.bytes_value(id.map_or(&[], |id| &id[..])));
+    envelope_values.push(version.bytes_value(signature.map_or(&[], |signature| &signature[..])));
+    canonical::to_vec(&CborValue::Array(envelope_values))
 }
 
 fn get_canonical_representation(
+    version: ProtocolVersion,
     verification_key: &Bytes32,
     encryption_key: &Bytes32,
     payload: CborValue,
+    parent: Option<Bytes32>,
+    validity: Validity,
 ) -> Result<Vec<u8>, GxtError> {
-    cbor_array(verification_key, encryption_key, payload, None, None, None)
+    let parent = if version.authenticates_parent {
+        parent
+    } else {
+        None
+    };
+    cbor_array(
+        version,
+        verification_key,
+        encryption_key,
+        payload,
+        parent,
+        validity,
+        None,
+        None,
+    )
 }
 
 fn preimage(canonical: &[u8]) -> Vec<u8> {
@@ -486,16 +1789,60 @@ fn preimage(canonical: &[u8]) -> Vec<u8> {
     v
 }
 
+/// How a new token is compressed and written as text.
+#[derive(Clone, Debug)]
+struct TokenOptions {
+    encoding: Encoding,
+    dictionary: Option<Dictionary>,
+    /// Whether the plaintext of encrypted messages is compressed as well
+    compress_plaintext: bool,
+}
+
+impl Default for TokenOptions {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::default(),
+            dictionary: compression_dictionary(),
+            compress_plaintext: false,
+        }
+    }
+}
+
 fn make(
     key: &SigningKey,
     kind: PayloadKind,
     payload: CborValue,
     parent: Option<Bytes32>,
+    validity: Validity,
+) -> Result<String, GxtError> {
+    make_with_options(
+        key,
+        kind,
+        payload,
+        parent,
+        validity,
+        &TokenOptions::default(),
+    )
+}
+
+fn make_with_options(
+    key: &SigningKey,
+    kind: PayloadKind,
+    payload: CborValue,
+    parent: Option<Bytes32>,
+    validity: Validity,
+    options: &TokenOptions,
 ) -> Result<String, GxtError> {
     let verification_key = key.verifying_key().to_bytes();
     let (_, encryption_key) = derive_enc_from_signing(key);
-    let canonical =
-        get_canonical_representation(&verification_key, &encryption_key, payload.clone())?;
+    let canonical = get_canonical_representation(
+        ProtocolVersion::for_new_tokens(),
+        &verification_key,
+        &encryption_key,
+        payload.clone(),
+        parent,
+        validity,
+    )?;
 
     let id = blake3::hash(&canonical);
     let signature = key.sign(&preimage(&canonical));
@@ -506,20 +1853,25 @@ fn make(
         kind,
         payload,
         parent,
+        validity,
         id.as_bytes(),
         &signature.to_bytes(),
+        options,
     )
 }
 
-fn make_prefix(kind: PayloadKind) -> String {
-    format!(
-        "{PREFIX}{}:",
-        match kind {
-            PayloadKind::Id => "i",
-            PayloadKind::Msg => "m",
-            PayloadKind::Key => "k",
-        }
-    )
+/// The character of the payload kind in the prefix.
+fn kind_code(kind: PayloadKind) -> &'static str {
+    match kind {
+        PayloadKind::Id => "i",
+        PayloadKind::Msg => "m",
+        PayloadKind::Key => "k",
+        PayloadKind::EncryptedKey => "e",
+        PayloadKind::Rotation => "r",
+        PayloadKind::Revocation => "v",
+        PayloadKind::Public => "p",
+        PayloadKind::Signature => "s",
+    }
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -529,56 +1881,186 @@ fn encode_message(
     kind: PayloadKind,
     payload: CborValue,
     parent: Option<Bytes32>,
+    validity: Validity,
     id: &Bytes32,
     signature: &Bytes64,
+    options: &TokenOptions,
 ) -> Result<String, GxtError> {
     let envelope_cbor = cbor_array(
+        ProtocolVersion::for_new_tokens(),
         verification_key,
         encryption_key,
         payload,
         parent,
+        validity,
         Some(id),
         Some(signature),
     )?;
-    let compressed_message = zstd::encode_all(&envelope_cbor[..], 3)?;
-    Ok(format!(
-        "{}{}",
-        make_prefix(kind),
-        bs58::encode(compressed_message).into_string()
-    ))
+    let limits = Limits::default();
+    if envelope_cbor.len() > limits.max_decompressed_size {
+        return Err(GxtError::DecompressedTooLarge {
+            limit: limits.max_decompressed_size,
+        });
+    }
+    let compressed_message = dictionary::compress(&envelope_cbor, options.dictionary.as_ref())?;
+    encode_compressed(kind, options.encoding, &compressed_message, &limits)
+}
+
+fn encode_compressed(
+    kind: PayloadKind,
+    encoding: Encoding,
+    compressed_message: &[u8],
+    limits: &Limits,
+) -> Result<String, GxtError> {
+    if compressed_message.len() > limits.max_compressed_size {
+        return Err(GxtError::CompressedTooLarge {
+            limit: limits.max_compressed_size,
+            got: compressed_message.len(),
+        });
+    }
+    let token = encoding.encode(kind, compressed_message);
+    if token.len() > limits.max_token_len {
+        return Err(GxtError::TokenTooLong {
+            limit: limits.max_token_len,
+            got: token.len(),
+        });
+    }
+    Ok(token)
 }
 
-fn get_kind(message: &str) -> Result<(PayloadKind, &str), GxtError> {
-    let rest = message.strip_prefix(PREFIX).ok_or(GxtError::BadPrefix)?;
-    let (left, right) = rest.split_once(':').ok_or(GxtError::BadPrefix)?;
-    Ok((PayloadKind::from_str(left)?, right))
+/// Decodes the text of a token into the compressed envelope.
+fn decode_compressed(message: &str, limits: &Limits) -> Result<(PayloadKind, Vec<u8>), GxtError> {
+    let message = message.trim();
+    if message.len() > limits.max_token_len {
+        return Err(GxtError::TokenTooLong {
+            limit: limits.max_token_len,
+            got: message.len(),
+        });
+    }
+    let (kind, encoding, body) = split_token(message)?;
+    // Decoding Base58 takes quadratic time, so data that can not fit the limit is never decoded.
+    let max_body_len = encoding.max_encoded_len(limits.max_compressed_size);
+    if body.len() > max_body_len {
+        return Err(GxtError::TokenTooLong {
+            limit: message.len() - body.len() + max_body_len,
+            got: message.len(),
+        });
+    }
+    let compressed_message = encoding.decode(kind, body)?;
+    if compressed_message.len() > limits.max_compressed_size {
+        return Err(GxtError::CompressedTooLarge {
+            limit: limits.max_compressed_size,
+            got: compressed_message.len(),
+        });
+    }
+    Ok((kind, compressed_message))
 }
 
-fn decode_message(message: &str) -> Result<Vec<u8>, GxtError> {
-    let compressed_message = bs58::decode(message).into_vec()?;
-    let raw = zstd::decode_all(&compressed_message[..])?;
+/// Decompresses the data, but stops as soon as more than `limit` bytes were produced.
+fn decompress(compressed_message: &[u8], limit: usize) -> Result<Vec<u8>, GxtError> {
+    let dictionary = zstd::zstd_safe::get_dict_id_from_frame(compressed_message)
+        .map(|id| dictionary::dictionary(id.get()))
+        .transpose()?;
+    let reader = std::io::BufReader::new(compressed_message);
+    let mut decoder = match &dictionary {
+        Some(dictionary) => {
+            zstd::stream::read::Decoder::with_dictionary(reader, dictionary.as_bytes())?
+        }
+        None => zstd::stream::read::Decoder::with_buffer(reader)?,
+    };
+    // The window never has to be larger than the data we are willing to accept,
+    // so hostile frames can not make us allocate huge buffers up front.
+    // Tokens are compressed with level 3, which uses a window of up to 2^21 bytes.
+    let window_log = limit
+        .checked_next_power_of_two()
+        .map_or(31, usize::trailing_zeros)
+        .clamp(21, 31);
+    decoder.window_log_max(window_log)?;
+    let mut raw = Vec::new();
+    decoder.take(limit as u64 + 1).read_to_end(&mut raw)?;
+    if raw.len() > limit {
+        return Err(GxtError::DecompressedTooLarge { limit });
+    }
     Ok(raw)
 }
 
+/// Decodes a token into the fields of its envelope, without interpreting them.
+fn decode_envelope_values(
+    msg: &str,
+    limits: &Limits,
+) -> Result<(PayloadKind, Vec<CborValue>), GxtError> {
+    let (kind, compressed_message) = decode_compressed(msg, limits)?;
+    let raw = decompress(&compressed_message, limits.max_decompressed_size)?;
+    let envelope_cbor: CborValue = serde_cbor::from_slice(&raw)?;
+
+    let CborValue::Array(values) = envelope_cbor else {
+        return Err(GxtError::Invalid);
+    };
+    Ok((kind, values))
+}
+
+fn parse_version(value: Option<CborValue>) -> Result<u8, GxtError> {
+    match value {
+        Some(CborValue::Integer(i)) => u8::try_from(i).map_err(|_| GxtError::Invalid),
+        _ => Err(GxtError::Invalid),
+    }
+}
+
 fn parse_hex<const SIZE: usize>(hex_string: &str) -> Result<[u8; SIZE], GxtError> {
-    let unsized_hex = hex::decode(hex_string)?;
-
-    let got = unsized_hex.len();
-    let hex: [u8; SIZE] = unsized_hex
-        .try_into()
-        .map_err(|_| GxtError::InvalidHexSize {
-            expected: SIZE,
-            got,
-        })?;
-    Ok(hex)
-}
-
-fn parse_key(key: &str) -> Result<SigningKey, GxtError> {
-    if key.starts_with(PREFIX) {
-        let token = verify_message::<JsonValue>(key.trim())?;
-        Ok(from_value(token.payload)?)
-    } else {
-        Ok(SigningKey::from_bytes(&parse_hex::<32>(key)?))
+    to_array(hex::decode(hex_string)?)
+}
+
+fn parse_field<const SIZE: usize>(
+    version: ProtocolVersion,
+    value: Option<CborValue>,
+) -> Result<[u8; SIZE], GxtError> {
+    to_array(version.parse_bytes(value)?)
+}
+
+fn to_array<const SIZE: usize>(bytes: Vec<u8>) -> Result<[u8; SIZE], GxtError> {
+    let got = bytes.len();
+    bytes.try_into().map_err(|_| GxtError::InvalidHexSize {
+        expected: SIZE,
+        got,
+    })
+}
+
+/// Whether the payload of this kind is created by the protocol itself, rather than by the game.
+fn has_protocol_payload(kind: PayloadKind) -> bool {
+    matches!(
+        kind,
+        PayloadKind::Msg
+            | PayloadKind::EncryptedKey
+            | PayloadKind::Rotation
+            | PayloadKind::Revocation
+            | PayloadKind::Signature
+    )
+}
+
+/// Replaces all byte strings with hex encoded text, so protocol payloads look the same,
+/// no matter which version stored them.
+fn hex_fields(value: CborValue) -> CborValue {
+    match value {
+        CborValue::Bytes(bytes) => CborValue::Text(hex::encode(bytes)),
+        CborValue::Array(values) => CborValue::Array(values.into_iter().map(hex_fields).collect()),
+        CborValue::Map(map) => CborValue::Map(
+            map.into_iter()
+                .map(|(key, value)| (key, hex_fields(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn timestamp_value(timestamp: Option<u64>) -> CborValue {
+    timestamp.map_or(CborValue::Null, |t| CborValue::Integer(t.into()))
+}
+
+fn parse_timestamp(value: Option<CborValue>) -> Result<Option<u64>, GxtError> {
+    match value {
+        Some(CborValue::Null) => Ok(None),
+        Some(CborValue::Integer(t)) => Ok(Some(u64::try_from(t).map_err(|_| GxtError::Invalid)?)),
+        _ => Err(GxtError::Invalid),
     }
 }
 
@@ -590,10 +2072,59 @@ fn derive_enc_from_signing(key: &SigningKey) -> (Bytes32, Bytes32) {
     (secret_key.to_bytes(), encryption_key.to_bytes())
 }
 
-fn enc_derive_key_from_pairs(my_secret_key: &Bytes32, their_encryption_key: &Bytes32) -> Key {
+/// What a key derived by [`derive_message_key`] is used for.
+#[derive(Clone, Copy)]
+enum KeyPurpose {
+    /// Encrypts the payload of a message with a single recipient
+    Payload,
+    /// Encrypts the content key of a message with multiple recipients
+    ContentKey,
+}
+
+/// Generates the ephemeral key pair of a message, if the key agreement needs one.
+fn generate_ephemeral_key(agreement: KeyAgreement) -> Option<(Bytes32, Bytes32)> {
+    if agreement == KeyAgreement::Static {
+        return None;
+    }
+    let secret_key = XSecret::from(random::bytes::<32>());
+    let public_key = XPublicKey::from(&secret_key);
+    Some((secret_key.to_bytes(), public_key.to_bytes()))
+}
+
+/// Derives the key of a message from pairs of (my secret key, their public key).
+///
+/// The sender passes its ephemeral secret key and the recipient's key as `ephemeral_keys`,
+/// the recipient passes its own secret key and the ephemeral public key of the message.
+fn derive_message_key(
+    purpose: KeyPurpose,
+    agreement: KeyAgreement,
+    static_keys: (&Bytes32, &Bytes32),
+    ephemeral_keys: Option<(&Bytes32, &Bytes32)>,
+) -> Result<Key, GxtError> {
+    let context = match (purpose, agreement) {
+        (KeyPurpose::Payload, KeyAgreement::Static) => "GXT-ENC-XCHACHA20POLY1305",
+        (KeyPurpose::Payload, KeyAgreement::Ephemeral) => "GXT-ENC-ES-XCHACHA20POLY1305",
+        (KeyPurpose::Payload, KeyAgreement::EphemeralStatic) => "GXT-ENC-ES-SS-XCHACHA20POLY1305",
+        (KeyPurpose::ContentKey, KeyAgreement::Static) => "GXT-WRAP-XCHACHA20POLY1305",
+        (KeyPurpose::ContentKey, KeyAgreement::Ephemeral) => "GXT-WRAP-ES-XCHACHA20POLY1305",
+        (KeyPurpose::ContentKey, KeyAgreement::EphemeralStatic) => {
+            "GXT-WRAP-ES-SS-XCHACHA20POLY1305"
+        }
+    };
+    let mut hasher = blake3::Hasher::new_derive_key(context);
+    if agreement != KeyAgreement::Static {
+        let (my_secret_key, their_public_key) = ephemeral_keys.ok_or(GxtError::Invalid)?;
+        hasher.update(&diffie_hellman(my_secret_key, their_public_key));
+    }
+    if agreement != KeyAgreement::Ephemeral {
+        let (my_secret_key, their_public_key) = static_keys;
+        hasher.update(&diffie_hellman(my_secret_key, their_public_key));
+    }
+    Ok(Key::from_slice(hasher.finalize().as_bytes()).to_owned())
+}
+
+fn diffie_hellman(my_secret_key: &Bytes32, their_public_key: &Bytes32) -> Bytes32 {
     let key = XSecret::from(*my_secret_key);
-    let verification_key = XPublicKey::from(*their_encryption_key);
-    let shared = key.diffie_hellman(&verification_key);
-    let derived_key = blake3::derive_key("GXT-ENC-XCHACHA20POLY1305", shared.as_bytes());
-    Key::from_slice(&derived_key).to_owned()
+    let public_key = XPublicKey::from(*their_public_key);
+    key.diffie_hellman(&public_key).to_bytes()
 }
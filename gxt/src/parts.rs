@@ -0,0 +1,430 @@
+use std::collections::BTreeMap;
+
+use crate::{Encoding, GxtError, Limits, PREFIX, PayloadKind, Token, kind_code, parse_hex};
+
+/// Length of the hex encoded group and checksum of a part.
+const HASH_HEX_LEN: usize = 8;
+
+/// Splits a token into parts that are at most `max_part_len` characters long.
+pub(crate) fn split(token: &Token, max_part_len: usize) -> Result<Vec<String>, GxtError> {
+    let text = token.as_str();
+    let group = group_of(text);
+    let mut total = 1;
+    let chunk_len = loop {
+        // index and total use at most as many digits as total
+        let header_len = PREFIX.len() + 1 + 2 * digits(total) + 2 + 2 * HASH_HEX_LEN + 2;
+        if max_part_len <= header_len {
+            return Err(GxtError::PartLimitTooSmall {
+                limit: max_part_len,
+            });
+        }
+        let chunk_len = max_part_len - header_len;
+        if text.len().div_ceil(chunk_len) <= total {
+            break chunk_len;
+        }
+        total = text.len().div_ceil(chunk_len);
+    };
+
+    let chunks = text.as_bytes().chunks(chunk_len).collect::<Vec<_>>();
+    let total = chunks.len();
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let chunk = std::str::from_utf8(chunk).map_err(|_| GxtError::Invalid)?;
+            let index = i + 1;
+            let part = format!(
+                "{PREFIX}{}{index}/{total}:{}.{chunk}.{}",
+                kind_code(token.kind()),
+                hex::encode(group),
+                hex::encode(checksum(&group, index, total, chunk)),
+            );
+            // Keep the whole part in the alphanumeric mode of QR codes.
+            if token.encoding() == Encoding::Qr {
+                Ok(part.to_ascii_uppercase())
+            } else {
+                Ok(part)
+            }
+        })
+        .collect()
+}
+
+/// Collects the parts of a token that was split with [`Token::split_into_parts`].
+///
+/// Parts can be inserted in any order. Once all parts are there,
+/// [`TokenParts::assemble`] returns the original token.
+///
+/// The parts come from untrusted input, so their number and the length of the joined token
+/// are bounded by [`Limits::max_token_len`].
+#[derive(Clone, Debug, Default)]
+pub struct TokenParts {
+    header: Option<PartsHeader>,
+    chunks: BTreeMap<usize, String>,
+    /// The length of all chunks
+    len: usize,
+    limits: Limits,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PartsHeader {
+    kind: PayloadKind,
+    group: [u8; 4],
+    total: usize,
+}
+
+impl TokenParts {
+    /// Creates an empty collection of parts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty collection of parts,
+    /// using custom size limits instead of the default ones.
+    pub fn with_limits(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Adds a part after checking its checksum.
+    ///
+    /// Returns `false` if the part was already added.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::BadChecksum`] if the part was changed, e.g. by a typo.
+    /// - returns [`GxtError::PartMismatch`] if the part belongs to a different token than the parts added before.
+    /// - returns [`GxtError::TooManyParts`] if the part claims more parts than a token within the limits has.
+    /// - returns [`GxtError::TokenTooLong`] if the joined token would be longer than the limit.
+    /// - returns a corresponding [`GxtError`], if the part can not be parsed.
+    pub fn insert(&mut self, part: &str) -> Result<bool, GxtError> {
+        let (header, index, chunk) = parse_part(part.trim(), &self.limits)?;
+        if self.header.is_some_and(|known| known != header) {
+            return Err(GxtError::PartMismatch);
+        }
+        if self.chunks.contains_key(&index) {
+            return Ok(false);
+        }
+        let len = self.len + chunk.len();
+        if len > self.limits.max_token_len {
+            return Err(GxtError::TokenTooLong {
+                limit: self.limits.max_token_len,
+                got: len,
+            });
+        }
+        self.header = Some(header);
+        self.chunks.insert(index, chunk.to_string());
+        self.len = len;
+        Ok(true)
+    }
+
+    /// The payload kind of the token, once a part was added.
+    pub fn kind(&self) -> Option<PayloadKind> {
+        self.header.map(|header| header.kind)
+    }
+
+    /// The number of parts of the token, once a part was added.
+    pub fn total(&self) -> Option<usize> {
+        self.header.map(|header| header.total)
+    }
+
+    /// The number of parts that were added.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Whether no part was added yet.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// The numbers of all parts that are still missing, starting at 1.
+    pub fn missing(&self) -> Vec<usize> {
+        let mut missing = Vec::new();
+        let mut next = 1;
+        for &index in self.chunks.keys() {
+            missing.extend(next..index);
+            next = index + 1;
+        }
+        missing.extend(next..=self.total().unwrap_or_default());
+        missing
+    }
+
+    /// Whether all parts were added.
+    pub fn is_complete(&self) -> bool {
+        self.total() == Some(self.chunks.len())
+    }
+
+    /// Joins the parts back into the original token.
+    ///
+    /// The token is **not** verified.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::MissingParts`] if not all parts were added.
+    /// - returns [`GxtError::BadChecksum`] if the joined token does not match the parts.
+    pub fn assemble(&self) -> Result<Token, GxtError> {
+        let Some(header) = self.header else {
+            return Err(GxtError::MissingParts {
+                missing: Vec::new(),
+                total: 0,
+            });
+        };
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(GxtError::MissingParts {
+                missing,
+                total: header.total,
+            });
+        }
+        let text = self.chunks.values().map(String::as_str).collect::<String>();
+        if group_of(&text) != header.group {
+            return Err(GxtError::BadChecksum);
+        }
+        let token = text.parse::<Token>()?;
+        if token.kind() != header.kind {
+            return Err(GxtError::PartMismatch);
+        }
+        Ok(token)
+    }
+}
+
+/// Whether the text is a part with a valid checksum, of a token within the limits.
+pub(crate) fn is_part(part: &str, limits: &Limits) -> bool {
+    parse_part(part, limits).is_ok()
+}
+
+/// Parses `gx<kind><index>/<total>:<group>.<chunk>.<checksum>` and checks the checksum.
+///
+/// The checksum only detects typos, anyone can compute it for a part with a made up `total`.
+/// Every part holds at least one character of the token, so a token within the limits
+/// has at most [`Limits::max_token_len`] parts.
+fn parse_part<'a>(
+    part: &'a str,
+    limits: &Limits,
+) -> Result<(PartsHeader, usize, &'a str), GxtError> {
+    let (prefix, rest) = part.split_once(':').ok_or(GxtError::BadPrefix)?;
+    let prefix = prefix.to_ascii_lowercase();
+    let numbers = prefix.strip_prefix(PREFIX).ok_or(GxtError::BadPrefix)?;
+    let code_len = numbers
+        .find(|c: char| c.is_ascii_digit())
+        .ok_or(GxtError::BadPrefix)?;
+    let (code, numbers) = numbers.split_at(code_len);
+    let kind = code.parse::<PayloadKind>()?;
+    let (index, total) = numbers.split_once('/').ok_or(GxtError::BadPrefix)?;
+    let index = index.parse::<usize>().map_err(|_| GxtError::BadPrefix)?;
+    let total = total.parse::<usize>().map_err(|_| GxtError::BadPrefix)?;
+    if index == 0 || index > total {
+        return Err(GxtError::BadPrefix);
+    }
+    if total > limits.max_token_len {
+        return Err(GxtError::TooManyParts {
+            limit: limits.max_token_len,
+            got: total,
+        });
+    }
+
+    let (group, rest) = rest.split_once('.').ok_or(GxtError::Invalid)?;
+    let (chunk, expected) = rest.rsplit_once('.').ok_or(GxtError::Invalid)?;
+    let group = parse_hex::<4>(&group.to_ascii_lowercase())?;
+    let expected = parse_hex::<4>(&expected.to_ascii_lowercase())?;
+    if checksum(&group, index, total, chunk) != expected {
+        return Err(GxtError::BadChecksum);
+    }
+    Ok((PartsHeader { kind, group, total }, index, chunk))
+}
+
+/// The shared id of all parts of a token.
+fn group_of(token: &str) -> [u8; 4] {
+    truncate(blake3::hash(token.as_bytes()).as_bytes())
+}
+
+fn checksum(group: &[u8; 4], index: usize, total: usize, chunk: &str) -> [u8; 4] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(group);
+    hasher.update(&(index as u64).to_be_bytes());
+    hasher.update(&(total as u64).to_be_bytes());
+    hasher.update(chunk.as_bytes());
+    truncate(hasher.finalize().as_bytes())
+}
+
+fn truncate(hash: &[u8; 32]) -> [u8; 4] {
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+fn digits(n: usize) -> usize {
+    n.to_string().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Identity;
+
+    fn token(encoding: Encoding) -> Token {
+        Identity::generate()
+            .message()
+            .payload(&"x".repeat(300))
+            .dictionary(None)
+            .encoding(encoding)
+            .sign()
+            .unwrap()
+    }
+
+    fn collect<'a>(parts: impl IntoIterator<Item = &'a String>) -> TokenParts {
+        let mut token_parts = TokenParts::new();
+        for part in parts {
+            assert!(token_parts.insert(part).unwrap());
+        }
+        token_parts
+    }
+
+    #[test]
+    fn parts_fit_the_limit_and_join_in_any_order() {
+        for &encoding in Encoding::all() {
+            let token = token(encoding);
+            let mut parts = token.split_into_parts(120).unwrap();
+            assert!(parts.len() > 2);
+            assert!(parts.iter().all(|part| part.len() <= 120));
+            parts.rotate_left(1);
+            parts.swap(0, 1);
+            let joined = collect(&parts).assemble().unwrap();
+            assert_eq!(joined.as_str(), token.as_str());
+        }
+    }
+
+    #[test]
+    fn qr_parts_are_upper_case() {
+        let parts = token(Encoding::Qr).split_into_parts(120).unwrap();
+        assert!(
+            parts
+                .iter()
+                .all(|part| part.chars().all(|c| !c.is_ascii_lowercase()))
+        );
+    }
+
+    #[test]
+    fn duplicate_parts_are_ignored() {
+        let parts = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let mut token_parts = collect(&parts[..1]);
+        assert!(!token_parts.insert(&parts[0]).unwrap());
+        assert!(!token_parts.insert(&format!(" {}\n", parts[0])).unwrap());
+        assert_eq!(token_parts.len(), 1);
+    }
+
+    #[test]
+    fn missing_parts_are_reported() {
+        let parts = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let total = parts.len();
+        let token_parts = collect(parts.iter().skip(1).step_by(2));
+        let missing = (1..=total).step_by(2).collect::<Vec<_>>();
+        assert_eq!(token_parts.missing(), missing);
+        assert!(!token_parts.is_complete());
+        assert!(matches!(
+            token_parts.assemble(),
+            Err(GxtError::MissingParts { missing: m, total: t }) if m == missing && t == total
+        ));
+        assert!(matches!(
+            TokenParts::new().assemble(),
+            Err(GxtError::MissingParts { total: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn parts_of_other_tokens_are_rejected() {
+        let parts = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let others = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let mut token_parts = collect(&parts[..1]);
+        assert!(matches!(
+            token_parts.insert(&others[1]),
+            Err(GxtError::PartMismatch)
+        ));
+    }
+
+    #[test]
+    fn changed_parts_fail_the_checksum() {
+        let parts = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let part = &parts[1];
+        let dot = part.find('.').unwrap();
+        let mut typo = part.clone().into_bytes();
+        typo[dot + 1] = if typo[dot + 1] == b'2' { b'3' } else { b'2' };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(matches!(
+            TokenParts::new().insert(&typo),
+            Err(GxtError::BadChecksum)
+        ));
+        // moving a chunk to another index changes its checksum as well
+        let moved = part.replacen("2/", "3/", 1);
+        assert!(matches!(
+            TokenParts::new().insert(&moved),
+            Err(GxtError::BadChecksum)
+        ));
+    }
+
+    #[test]
+    fn indices_outside_the_total_are_rejected() {
+        for part in [
+            "gxm0/2:00000000.abc.00000000",
+            "gxm3/2:00000000.abc.00000000",
+        ] {
+            assert!(matches!(
+                TokenParts::new().insert(part),
+                Err(GxtError::BadPrefix)
+            ));
+        }
+    }
+
+    #[test]
+    fn totals_beyond_the_limits_are_rejected() {
+        // the checksum is valid, anyone can compute it
+        let part = "gxm1/18446744073709551615:00000000.abc.8c4ec46d";
+        assert!(matches!(
+            TokenParts::new().insert(part),
+            Err(GxtError::TooManyParts {
+                got: usize::MAX,
+                ..
+            })
+        ));
+        assert!(crate::join_parts(&[part]).is_err());
+
+        let limits = Limits {
+            max_token_len: 200,
+            ..Limits::default()
+        };
+        let parts = token(Encoding::Base58).split_into_parts(120).unwrap();
+        let mut token_parts = TokenParts::with_limits(limits);
+        assert!(matches!(
+            parts
+                .iter()
+                .try_for_each(|part| token_parts.insert(part).map(|_| ())),
+            Err(GxtError::TokenTooLong { limit: 200, .. })
+        ));
+        assert!(token_parts.len() < parts.len());
+    }
+
+    #[test]
+    fn missing_parts_of_a_large_total_are_listed_without_the_added_ones() {
+        let total = Limits::default().max_token_len;
+        let chunk = "abc";
+        let group = [0; 4];
+        let part = |index: usize| {
+            format!(
+                "gxm{index}/{total}:00000000.{chunk}.{}",
+                hex::encode(checksum(&group, index, total, chunk))
+            )
+        };
+        let token_parts = collect(&[part(2), part(total)]);
+        let missing = token_parts.missing();
+        assert_eq!(missing.len(), total - 2);
+        assert_eq!(missing[..2], [1, 3]);
+        assert_eq!(missing.last(), Some(&(total - 1)));
+        assert!(!token_parts.is_complete());
+    }
+
+    #[test]
+    fn limits_without_room_for_data_are_rejected() {
+        assert!(matches!(
+            token(Encoding::Base58).split_into_parts(25),
+            Err(GxtError::PartLimitTooSmall { limit: 25 })
+        ));
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::Key;
+use ed25519_dalek::SigningKey;
+use serde_cbor::Value as CborValue;
+
+use crate::{GxtError, ProtocolVersion, open, parse_hex, random, seal};
+
+const KDF: &str = "argon2id";
+const ALGORITHM: &str = "xchacha20poly1305";
+/// Memory cost of newly encrypted keys in KiB.
+const MEMORY_COST: u32 = 64 * 1024;
+/// Number of passes of newly encrypted keys.
+const TIME_COST: u32 = 3;
+/// Degree of parallelism of newly encrypted keys.
+const PARALLELISM: u32 = 1;
+/// Upper bound for the memory cost of keys we are willing to unlock, so a hostile
+/// token can not make us allocate an arbitrary amount of memory.
+const MAX_MEMORY_COST: u32 = 256 * 1024;
+/// Upper bound for the number of passes of keys we are willing to unlock.
+const MAX_TIME_COST: u32 = 8;
+
+/// Encrypts the private key with a key derived from the password.
+pub(crate) fn lock(signing_key: &SigningKey, password: &str) -> Result<CborValue, GxtError> {
+    let salt = random::bytes::<16>();
+    let key = derive_key(password, &salt, MEMORY_COST, TIME_COST, PARALLELISM)?;
+    let verification_key = signing_key.verifying_key().to_bytes();
+    let (nonce, cipher_text) = seal(&key, &signing_key.to_bytes(), &verification_key)?;
+
+    let version = ProtocolVersion::for_new_tokens();
+    let mut locked = BTreeMap::new();
+    locked.insert(CborValue::Text("kdf".into()), CborValue::Text(KDF.into()));
+    locked.insert(CborValue::Text("salt".into()), version.bytes_value(&salt));
+    locked.insert(
+        CborValue::Text("m".into()),
+        CborValue::Integer(MEMORY_COST.into()),
+    );
+    locked.insert(
+        CborValue::Text("t".into()),
+        CborValue::Integer(TIME_COST.into()),
+    );
+    locked.insert(
+        CborValue::Text("p".into()),
+        CborValue::Integer(PARALLELISM.into()),
+    );
+    locked.insert(
+        CborValue::Text("alg".into()),
+        CborValue::Text(ALGORITHM.into()),
+    );
+    locked.insert(CborValue::Text("n24".into()), version.bytes_value(&nonce));
+    locked.insert(
+        CborValue::Text("ct".into()),
+        version.bytes_value(&cipher_text),
+    );
+    Ok(CborValue::Map(locked))
+}
+
+/// Decrypts the private key stored in the payload of an encrypted key token.
+///
+/// The `verification_key` of the envelope is used as associated data,
+/// so the encrypted key can not be moved into a token signed by a different key.
+pub(crate) fn unlock(
+    payload: &CborValue,
+    verification_key: &[u8; 32],
+    password: &str,
+) -> Result<SigningKey, GxtError> {
+    let CborValue::Map(locked) = payload else {
+        return Err(GxtError::Invalid);
+    };
+    let text = |name: &str| match locked.get(&CborValue::Text(name.into())) {
+        Some(CborValue::Text(t)) => Ok(t.as_str()),
+        _ => Err(GxtError::Invalid),
+    };
+    let cost = |name: &'static str, max: u32| match locked.get(&CborValue::Text(name.into())) {
+        Some(CborValue::Integer(i)) if *i < 1 => Err(GxtError::Invalid),
+        Some(CborValue::Integer(i)) => u32::try_from(*i).ok().filter(|cost| *cost <= max).ok_or(
+            GxtError::PasswordCostTooHigh {
+                parameter: name,
+                limit: max,
+                got: u64::try_from(*i).unwrap_or(u64::MAX),
+            },
+        ),
+        _ => Err(GxtError::Invalid),
+    };
+    if text("kdf")? != KDF || text("alg")? != ALGORITHM {
+        return Err(GxtError::Invalid);
+    }
+    let salt = parse_hex::<16>(text("salt")?)?;
+    let nonce = parse_hex::<24>(text("n24")?)?;
+    let cipher_text = hex::decode(text("ct")?)?;
+    let key = derive_key(
+        password,
+        &salt,
+        cost("m", MAX_MEMORY_COST)?,
+        cost("t", MAX_TIME_COST)?,
+        cost("p", u8::MAX.into())?,
+    )?;
+
+    let seed =
+        open(&key, &nonce, &cipher_text, verification_key).map_err(|_| GxtError::BadPassword)?;
+    let seed: [u8; 32] = seed.try_into().map_err(|_| GxtError::Invalid)?;
+    let signing_key = SigningKey::from_bytes(&seed);
+    if signing_key.verifying_key().to_bytes() != *verification_key {
+        return Err(GxtError::Invalid);
+    }
+    Ok(signing_key)
+}
+
+fn derive_key(
+    password: &str,
+    salt: &[u8],
+    memory_cost: u32,
+    time_cost: u32,
+    parallelism: u32,
+) -> Result<Key, GxtError> {
+    let params = Params::new(memory_cost, time_cost, parallelism, Some(32))
+        .map_err(|e| GxtError::Encryption(e.to_string()))?;
+    let mut key = Key::default();
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| GxtError::Encryption(e.to_string()))?;
+    Ok(key)
+}
@@ -0,0 +1,20 @@
+//! The source of all random numbers of this crate: keys, salts and nonces.
+
+use rand::RngCore;
+use rand::rngs::OsRng;
+
+/// Fills the buffer with random bytes.
+pub(crate) fn fill(bytes: &mut [u8]) {
+    #[cfg(feature = "test-vectors")]
+    if crate::test_vectors::fill(bytes) {
+        return;
+    }
+    OsRng.fill_bytes(bytes);
+}
+
+/// Returns `N` random bytes.
+pub(crate) fn bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    fill(&mut bytes);
+    bytes
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::GxtError;
+
+/// Keeps track of which messages were already redeemed.
+///
+/// Messages are identified by [`Envelope::id`](crate::Envelope::id), so the same token
+/// can only be redeemed once, no matter how often it gets pasted into the game.
+pub trait ReplayGuard {
+    /// Whether the message with the given id was already redeemed.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], if the store can not be read.
+    fn contains(&self, id: &str) -> Result<bool, GxtError>;
+
+    /// Marks the message with the given id as redeemed.
+    ///
+    /// Returns `false` if the id was already redeemed before.
+    ///
+    /// # Errors
+    /// - returns a corresponding [`GxtError`], if the store can not be written.
+    fn insert(&mut self, id: &str) -> Result<bool, GxtError>;
+}
+
+/// A [`ReplayGuard`] that only lives as long as the process.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryReplayGuard {
+    ids: HashSet<String>,
+}
+
+impl MemoryReplayGuard {
+    /// Creates an empty replay guard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ReplayGuard for MemoryReplayGuard {
+    fn contains(&self, id: &str) -> Result<bool, GxtError> {
+        Ok(self.ids.contains(id))
+    }
+
+    fn insert(&mut self, id: &str) -> Result<bool, GxtError> {
+        Ok(self.ids.insert(id.to_string()))
+    }
+}
+
+/// A [`ReplayGuard`] that appends the ids of redeemed messages to a file, one per line.
+///
+/// Store the file next to the save game, so a redeemed message stays redeemed
+/// after the game was restarted.
+#[derive(Debug)]
+pub struct FileReplayGuard {
+    path: PathBuf,
+    ids: HashSet<String>,
+    file: File,
+}
+
+impl FileReplayGuard {
+    /// Opens the replay store at the given path, creating it if it does not exist.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::ReplayStore`], if the file can not be opened or read.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, GxtError> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(GxtError::ReplayStore)?;
+        let mut ids = HashSet::new();
+        for line in BufReader::new(&file).lines() {
+            let line = line.map_err(GxtError::ReplayStore)?;
+            let id = line.trim();
+            if !id.is_empty() {
+                ids.insert(id.to_string());
+            }
+        }
+        Ok(Self { path, ids, file })
+    }
+
+    /// The path of the replay store.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl ReplayGuard for FileReplayGuard {
+    fn contains(&self, id: &str) -> Result<bool, GxtError> {
+        Ok(self.ids.contains(id))
+    }
+
+    fn insert(&mut self, id: &str) -> Result<bool, GxtError> {
+        if self.ids.contains(id) {
+            return Ok(false);
+        }
+        writeln!(self.file, "{id}").map_err(GxtError::ReplayStore)?;
+        self.file.sync_data().map_err(GxtError::ReplayStore)?;
+        Ok(self.ids.insert(id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path in the temporary directory that is removed again when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("gxt-replay-{}-{name}", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn ids_are_only_redeemed_once() {
+        let mut guard = MemoryReplayGuard::new();
+        assert!(!guard.contains("a").unwrap());
+        assert!(guard.insert("a").unwrap());
+        assert!(!guard.insert("a").unwrap());
+        assert!(guard.contains("a").unwrap());
+        assert!(!guard.contains("b").unwrap());
+    }
+
+    #[test]
+    fn file_guard_remembers_ids_after_reopening() {
+        let path = TempPath::new("reopen");
+        {
+            let mut guard = FileReplayGuard::open(&path.0).unwrap();
+            assert!(guard.insert("a").unwrap());
+            assert!(guard.insert("b").unwrap());
+            assert!(!guard.insert("a").unwrap());
+        }
+        let mut guard = FileReplayGuard::open(&path.0).unwrap();
+        assert!(guard.contains("a").unwrap() && guard.contains("b").unwrap());
+        assert!(!guard.insert("b").unwrap());
+        assert!(guard.insert("c").unwrap());
+        assert_eq!(std::fs::read_to_string(&path.0).unwrap(), "a\nb\nc\n");
+    }
+
+    #[test]
+    fn file_guard_ignores_blank_lines_and_whitespace() {
+        let path = TempPath::new("whitespace");
+        std::fs::write(&path.0, "a\n\n  b \r\n\n").unwrap();
+        let mut guard = FileReplayGuard::open(&path.0).unwrap();
+        assert!(guard.contains("a").unwrap() && guard.contains("b").unwrap());
+        assert!(!guard.contains("").unwrap());
+        assert!(!guard.insert("b").unwrap());
+    }
+
+    #[test]
+    fn file_guard_reports_unreadable_stores() {
+        let dir = std::env::temp_dir();
+        assert!(matches!(
+            FileReplayGuard::open(&dir),
+            Err(GxtError::ReplayStore(_))
+        ));
+    }
+}
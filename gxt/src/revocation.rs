@@ -0,0 +1,205 @@
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fmt,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_cbor::Value as CborValue;
+
+use crate::{Envelope, GxtError, PayloadKind, Token};
+
+/// Why a key was revoked.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RevocationReason {
+    /// No reason was given
+    #[default]
+    Unspecified,
+    /// The key leaked or was stolen
+    Compromised,
+    /// The key was replaced by a new key
+    Superseded,
+    /// The key is no longer used
+    Retired,
+}
+
+impl RevocationReason {
+    /// The reason code stored in the token.
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Unspecified => 0,
+            Self::Compromised => 1,
+            Self::Superseded => 2,
+            Self::Retired => 3,
+        }
+    }
+
+    /// Looks up a reason code. Unknown codes are treated as [`RevocationReason::Unspecified`],
+    /// so newer reasons still revoke the key.
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => Self::Compromised,
+            2 => Self::Superseded,
+            3 => Self::Retired,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+impl fmt::Display for RevocationReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unspecified => write!(f, "unspecified"),
+            Self::Compromised => write!(f, "compromised"),
+            Self::Superseded => write!(f, "superseded"),
+            Self::Retired => write!(f, "retired"),
+        }
+    }
+}
+
+/// A verified revocation certificate, which permanently revokes the key that signed it.
+///
+/// A certificate does not expire, so it can be created right after the key
+/// and stored offline until it is needed.
+#[derive(Clone, Debug)]
+pub struct Revocation {
+    token: Token,
+    envelope: Envelope<CborValue>,
+    reason: RevocationReason,
+}
+
+impl Revocation {
+    /// The `gxv:` token of this revocation.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// The id of this revocation.
+    pub fn id(&self) -> &str {
+        &self.envelope.id
+    }
+
+    /// The hex encoded verification key that was revoked.
+    pub fn verification_key(&self) -> &str {
+        &self.envelope.verification_key
+    }
+
+    /// Why the key was revoked.
+    pub fn reason(&self) -> RevocationReason {
+        self.reason
+    }
+}
+
+impl FromStr for Revocation {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Token>()?.try_into()
+    }
+}
+
+impl TryFrom<Token> for Revocation {
+    type Error = GxtError;
+
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        if token.kind() != PayloadKind::Revocation {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::Revocation,
+                got: token.kind(),
+            });
+        }
+        let envelope = token.verify::<CborValue>()?;
+        let CborValue::Map(map) = &envelope.payload else {
+            return Err(GxtError::Invalid);
+        };
+        let reason = match map.get(&CborValue::Text("reason".into())) {
+            Some(CborValue::Integer(code)) => {
+                RevocationReason::from_code(u8::try_from(*code).map_err(|_| GxtError::Invalid)?)
+            }
+            _ => return Err(GxtError::Invalid),
+        };
+        Ok(Self {
+            token,
+            envelope,
+            reason,
+        })
+    }
+}
+
+impl fmt::Display for Revocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.token.fmt(f)
+    }
+}
+
+/// Creates the payload of a revocation.
+pub(crate) fn revocation_payload(reason: RevocationReason) -> CborValue {
+    let mut revocation = BTreeMap::new();
+    revocation.insert(
+        CborValue::Text("reason".into()),
+        CborValue::Integer(reason.code().into()),
+    );
+    CborValue::Map(revocation)
+}
+
+/// A set of verified revocations.
+///
+/// Pass it to [`crate::verify_message_with_revocations`] or [`crate::decrypt_message_with_revocations`]
+/// to reject every token signed by a revoked key.
+#[derive(Clone, Debug, Default)]
+pub struct Revocations {
+    revocations: BTreeMap<String, Revocation>,
+}
+
+impl Revocations {
+    /// Creates an empty set of revocations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a verified revocation.
+    ///
+    /// Returns `false` if the key was already revoked.
+    pub fn insert(&mut self, revocation: Revocation) -> bool {
+        match self
+            .revocations
+            .entry(revocation.verification_key().to_string())
+        {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(revocation);
+                true
+            }
+        }
+    }
+
+    /// Whether the given verification key was revoked.
+    pub fn is_revoked(&self, verification_key: &str) -> bool {
+        self.revocations.contains_key(verification_key)
+    }
+
+    /// The revocation of the given verification key, if it was revoked.
+    pub fn get(&self, verification_key: &str) -> Option<&Revocation> {
+        self.revocations.get(verification_key)
+    }
+
+    /// The number of revoked keys.
+    pub fn len(&self) -> usize {
+        self.revocations.len()
+    }
+
+    /// Whether no key was revoked.
+    pub fn is_empty(&self) -> bool {
+        self.revocations.is_empty()
+    }
+}
+
+impl FromIterator<Revocation> for Revocations {
+    fn from_iter<T: IntoIterator<Item = Revocation>>(iter: T) -> Self {
+        let mut revocations = Self::new();
+        for revocation in iter {
+            revocations.insert(revocation);
+        }
+        revocations
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+
+use ed25519_dalek::{Signature, Signer, VerifyingKey};
+use serde_cbor::Value as CborValue;
+
+use crate::{
+    Bytes32, Envelope, GxtError, Identity, PayloadKind, ProtocolVersion, Token, parse_hex,
+};
+
+const ROTATION_DOMAIN: &[u8] = b"GXT-ROTATE";
+
+/// A verified statement that a key was replaced by a successor key.
+///
+/// The statement is signed by the old key, and the successor key signs that it accepts
+/// the endorsement, so nobody can claim someone else's key as their successor.
+#[derive(Clone, Debug)]
+pub struct Rotation {
+    token: Token,
+    envelope: Envelope<CborValue>,
+    successor_verification_key: String,
+    successor_encryption_key: String,
+}
+
+impl Rotation {
+    /// The `gxr:` token of this rotation.
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// The id of this rotation.
+    pub fn id(&self) -> &str {
+        &self.envelope.id
+    }
+
+    /// Unix timestamp of when the rotation was created,
+    /// if it was created with [`Identity::rotate_to_with_validity`].
+    pub fn created_at(&self) -> Option<u64> {
+        self.envelope.created_at
+    }
+
+    /// The hex encoded verification key that was replaced.
+    pub fn verification_key(&self) -> &str {
+        &self.envelope.verification_key
+    }
+
+    /// The hex encoded encryption key that was replaced.
+    pub fn encryption_key(&self) -> &str {
+        &self.envelope.encryption_key
+    }
+
+    /// The hex encoded verification key that replaces the old one.
+    pub fn successor_verification_key(&self) -> &str {
+        &self.successor_verification_key
+    }
+
+    /// The hex encoded encryption key that replaces the old one.
+    pub fn successor_encryption_key(&self) -> &str {
+        &self.successor_encryption_key
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = GxtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<Token>()?.try_into()
+    }
+}
+
+impl TryFrom<Token> for Rotation {
+    type Error = GxtError;
+
+    fn try_from(token: Token) -> Result<Self, Self::Error> {
+        if token.kind() != PayloadKind::Rotation {
+            return Err(GxtError::UnexpectedKind {
+                expected: PayloadKind::Rotation,
+                got: token.kind(),
+            });
+        }
+        let envelope = token.verify::<CborValue>()?;
+        let CborValue::Map(map) = &envelope.payload else {
+            return Err(GxtError::Invalid);
+        };
+        let text = |name: &str| match map.get(&CborValue::Text(name.into())) {
+            Some(CborValue::Text(t)) => Ok(t.as_str()),
+            _ => Err(GxtError::Invalid),
+        };
+        let verification_key = parse_hex::<32>(&envelope.verification_key)?;
+        let successor_verification_key = parse_hex::<32>(text("vk")?)?;
+        let successor_encryption_key = parse_hex::<32>(text("ek")?)?;
+        let signature = Signature::from_bytes(&parse_hex::<64>(text("sig")?)?);
+        if successor_verification_key == verification_key {
+            return Err(GxtError::Invalid);
+        }
+        VerifyingKey::from_bytes(&successor_verification_key)
+            .map_err(|_| GxtError::Invalid)?
+            .verify_strict(
+                &preimage(
+                    &verification_key,
+                    &successor_verification_key,
+                    &successor_encryption_key,
+                ),
+                &signature,
+            )
+            .map_err(|_| GxtError::BadSig)?;
+        Ok(Self {
+            token,
+            envelope,
+            successor_verification_key: hex::encode(successor_verification_key),
+            successor_encryption_key: hex::encode(successor_encryption_key),
+        })
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.token.fmt(f)
+    }
+}
+
+/// Creates the payload of a rotation, including the signature of the successor.
+pub(crate) fn endorse(identity: &Identity, successor: &Identity) -> Result<CborValue, GxtError> {
+    let verification_key = identity.signing_key.verifying_key().to_bytes();
+    let successor_verification_key = successor.signing_key.verifying_key().to_bytes();
+    if successor_verification_key == verification_key {
+        return Err(GxtError::Invalid);
+    }
+    let signature = successor.signing_key.sign(&preimage(
+        &verification_key,
+        &successor_verification_key,
+        &successor.encryption_key,
+    ));
+
+    let mut rotation = BTreeMap::new();
+    rotation.insert(
+        CborValue::Text("vk".into()),
+        ProtocolVersion::for_new_tokens().bytes_value(&successor_verification_key),
+    );
+    rotation.insert(
+        CborValue::Text("ek".into()),
+        ProtocolVersion::for_new_tokens().bytes_value(&successor.encryption_key),
+    );
+    rotation.insert(
+        CborValue::Text("sig".into()),
+        ProtocolVersion::for_new_tokens().bytes_value(&signature.to_bytes()),
+    );
+    Ok(CborValue::Map(rotation))
+}
+
+fn preimage(
+    verification_key: &Bytes32,
+    successor_verification_key: &Bytes32,
+    successor_encryption_key: &Bytes32,
+) -> Vec<u8> {
+    let mut v = Vec::with_capacity(ROTATION_DOMAIN.len() + 3 * 32);
+    v.extend_from_slice(ROTATION_DOMAIN);
+    v.extend_from_slice(verification_key);
+    v.extend_from_slice(successor_verification_key);
+    v.extend_from_slice(successor_encryption_key);
+    v
+}
+
+/// A problem found while following the rotations of a key in [`KeyRotations`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RotationIssue {
+    /// A key was replaced by more than one successor.
+    Fork {
+        /// The verification key that was replaced
+        verification_key: String,
+        /// The verification keys of all successors
+        successors: Vec<String>,
+    },
+    /// Following the rotations leads back to a key that was already replaced.
+    Cycle {
+        /// The verification key that was reached twice
+        verification_key: String,
+    },
+}
+
+/// A set of verified rotations, e.g. all rotations received from the contacts of a player.
+///
+/// Use [`KeyRotations::latest`] to find out which key currently replaces a known verification key.
+/// A key that was replaced by more than one successor is treated as compromised,
+/// because only one of the rotations can come from its owner.
+#[derive(Clone, Debug, Default)]
+pub struct KeyRotations {
+    rotations: BTreeMap<String, Vec<Rotation>>,
+}
+
+impl KeyRotations {
+    /// Creates an empty set of rotations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a verified rotation.
+    ///
+    /// Returns `false` if the same rotation was already added.
+    pub fn insert(&mut self, rotation: Rotation) -> bool {
+        let successors = self
+            .rotations
+            .entry(rotation.verification_key().to_string())
+            .or_default();
+        if successors.iter().any(|known| {
+            known.successor_verification_key() == rotation.successor_verification_key()
+        }) {
+            return false;
+        }
+        successors.push(rotation);
+        true
+    }
+
+    /// The number of rotations in the set.
+    pub fn len(&self) -> usize {
+        self.rotations.values().map(Vec::len).sum()
+    }
+
+    /// Whether the set contains no rotations.
+    pub fn is_empty(&self) -> bool {
+        self.rotations.is_empty()
+    }
+
+    /// Whether the given verification key was replaced by a successor.
+    pub fn is_rotated(&self, verification_key: &str) -> bool {
+        self.rotations.contains_key(verification_key)
+    }
+
+    /// The rotations from the given verification key up to the key that replaces it today.
+    ///
+    /// The chain is empty if the key was never replaced.
+    ///
+    /// # Errors
+    /// - returns a [`RotationIssue`] if a key in the chain has more than one successor
+    ///   or the chain leads back to a key that was already replaced.
+    pub fn chain(&self, verification_key: &str) -> Result<Vec<&Rotation>, RotationIssue> {
+        let mut chain = Vec::new();
+        let mut visited = HashSet::from([verification_key]);
+        let mut current = verification_key;
+        while let Some(successors) = self.rotations.get(current) {
+            let [rotation] = successors.as_slice() else {
+                return Err(RotationIssue::Fork {
+                    verification_key: current.to_string(),
+                    successors: successors
+                        .iter()
+                        .map(|rotation| rotation.successor_verification_key().to_string())
+                        .collect(),
+                });
+            };
+            current = rotation.successor_verification_key();
+            if !visited.insert(current) {
+                return Err(RotationIssue::Cycle {
+                    verification_key: current.to_string(),
+                });
+            }
+            chain.push(rotation);
+        }
+        Ok(chain)
+    }
+
+    /// The last rotation of the chain of the given verification key,
+    /// which names the keys that should be used today.
+    ///
+    /// Returns `None` if the key was never replaced.
+    ///
+    /// # Errors
+    /// - returns a [`RotationIssue`] if the chain can not be followed, see [`KeyRotations::chain`].
+    pub fn latest(&self, verification_key: &str) -> Result<Option<&Rotation>, RotationIssue> {
+        Ok(self.chain(verification_key)?.pop())
+    }
+
+    /// Checks all rotations and reports every key with more than one successor.
+    pub fn issues(&self) -> Vec<RotationIssue> {
+        self.rotations
+            .iter()
+            .filter(|(_, successors)| successors.len() > 1)
+            .map(|(verification_key, successors)| RotationIssue::Fork {
+                verification_key: verification_key.clone(),
+                successors: successors
+                    .iter()
+                    .map(|rotation| rotation.successor_verification_key().to_string())
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl FromIterator<Rotation> for KeyRotations {
+    fn from_iter<T: IntoIterator<Item = Rotation>>(iter: T) -> Self {
+        let mut rotations = Self::new();
+        for rotation in iter {
+            rotations.insert(rotation);
+        }
+        rotations
+    }
+}
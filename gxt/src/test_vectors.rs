@@ -0,0 +1,66 @@
+use std::cell::{Cell, RefCell};
+
+use crate::ProtocolVersion;
+
+/// Key derivation context of the stream, see [`with_seed`].
+const CONTEXT: &str = "gxt test vectors";
+
+thread_local! {
+    static STREAM: RefCell<Option<blake3::OutputReader>> = const { RefCell::new(None) };
+    static VERSION: Cell<Option<ProtocolVersion>> = const { Cell::new(None) };
+}
+
+/// Restores the previous stream, even if the closure panics.
+struct Restore(Option<blake3::OutputReader>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        STREAM.with(|stream| *stream.borrow_mut() = self.0.take());
+    }
+}
+
+/// Runs `f` with deterministic instead of random numbers on the current thread.
+///
+/// All keys, salts and nonces are read from the output of BLAKE3 in key derivation mode
+/// with the context `"gxt test vectors"` and the seed as key material, in the order they are needed.
+/// Other implementations can reproduce the same stream to create the same tokens.
+pub fn with_seed<T>(seed: &[u8], f: impl FnOnce() -> T) -> T {
+    let reader = blake3::Hasher::new_derive_key(CONTEXT)
+        .update(seed)
+        .finalize_xof();
+    let _restore = Restore(STREAM.with(|stream| stream.borrow_mut().replace(reader)));
+    f()
+}
+
+/// Restores the previous version, even if the closure panics.
+struct RestoreVersion(Option<ProtocolVersion>);
+
+impl Drop for RestoreVersion {
+    fn drop(&mut self) {
+        VERSION.with(|version| version.set(self.0));
+    }
+}
+
+/// Runs `f` with tokens created in an older protocol version instead of the current one on the current thread.
+///
+/// This creates the tokens of older implementations, to check that they can still be verified and decrypted.
+pub fn with_version<T>(version: ProtocolVersion, f: impl FnOnce() -> T) -> T {
+    let _restore = RestoreVersion(VERSION.with(|previous| previous.replace(Some(version))));
+    f()
+}
+
+/// The version set by [`with_version`] on the current thread, if any.
+pub(crate) fn version() -> Option<ProtocolVersion> {
+    VERSION.with(Cell::get)
+}
+
+/// Fills the buffer from the stream of the current thread, if [`with_seed`] set one.
+pub(crate) fn fill(bytes: &mut [u8]) -> bool {
+    STREAM.with(|stream| match stream.borrow_mut().as_mut() {
+        Some(reader) => {
+            reader.fill(bytes);
+            true
+        }
+        None => false,
+    })
+}
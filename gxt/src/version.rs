@@ -0,0 +1,123 @@
+use serde_cbor::Value as CborValue;
+
+use crate::GxtError;
+
+/// The layout and guarantees of a protocol version.
+///
+/// Tokens of every version in [`ProtocolVersion::supported`] can still be verified,
+/// but new tokens are always created with [`ProtocolVersion::current`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ProtocolVersion {
+    /// The version number stored in the token
+    pub version: u8,
+    /// Whether the parent is covered by the id and signature
+    pub authenticates_parent: bool,
+    /// Whether the envelope contains `created_at` and `expires_at`
+    pub has_validity: bool,
+    /// Whether encrypted messages authenticate the header as associated data
+    pub authenticates_header: bool,
+    /// Whether keys, ids, signatures, nonces and ciphertexts are stored as CBOR byte strings
+    /// instead of hex encoded text
+    pub binary_fields: bool,
+}
+
+/// All versions that can be decoded, from oldest to newest.
+const VERSIONS: [ProtocolVersion; 5] = [
+    ProtocolVersion {
+        version: 4,
+        authenticates_parent: false,
+        has_validity: false,
+        authenticates_header: false,
+        binary_fields: false,
+    },
+    ProtocolVersion {
+        version: 5,
+        authenticates_parent: true,
+        has_validity: false,
+        authenticates_header: false,
+        binary_fields: false,
+    },
+    ProtocolVersion {
+        version: 6,
+        authenticates_parent: true,
+        has_validity: true,
+        authenticates_header: false,
+        binary_fields: false,
+    },
+    ProtocolVersion {
+        version: 7,
+        authenticates_parent: true,
+        has_validity: true,
+        authenticates_header: true,
+        binary_fields: false,
+    },
+    ProtocolVersion {
+        version: 8,
+        authenticates_parent: true,
+        has_validity: true,
+        authenticates_header: true,
+        binary_fields: true,
+    },
+];
+
+impl ProtocolVersion {
+    /// The version used for new tokens.
+    pub fn current() -> Self {
+        VERSIONS[VERSIONS.len() - 1]
+    }
+
+    /// All versions that can be decoded, from oldest to newest.
+    pub fn supported() -> &'static [ProtocolVersion] {
+        &VERSIONS
+    }
+
+    /// Looks up a version number.
+    ///
+    /// # Errors
+    /// - returns [`GxtError::UnsupportedVersion`] if the version can not be decoded.
+    pub fn get(version: u8) -> Result<Self, GxtError> {
+        VERSIONS
+            .iter()
+            .find(|known| known.version == version)
+            .copied()
+            .ok_or(GxtError::UnsupportedVersion(version))
+    }
+
+    /// The version new tokens are created in: the current one,
+    /// unless the test vectors asked for an older one.
+    pub(crate) fn for_new_tokens() -> Self {
+        #[cfg(feature = "test-vectors")]
+        if let Some(version) = crate::test_vectors::version() {
+            return version;
+        }
+        Self::current()
+    }
+
+    /// Whether this is the version used for new tokens.
+    pub fn is_current(&self) -> bool {
+        *self == Self::current()
+    }
+
+    /// The number of fields of the envelope after the version.
+    pub(crate) fn field_count(&self) -> usize {
+        if self.has_validity { 8 } else { 6 }
+    }
+
+    /// Encodes binary data the way this version stores it.
+    pub(crate) fn bytes_value(&self, bytes: &[u8]) -> CborValue {
+        if self.binary_fields {
+            CborValue::Bytes(bytes.to_vec())
+        } else {
+            CborValue::Text(hex::encode(bytes))
+        }
+    }
+
+    /// Decodes binary data stored by [`ProtocolVersion::bytes_value`].
+    pub(crate) fn parse_bytes(&self, value: Option<CborValue>) -> Result<Vec<u8>, GxtError> {
+        match value {
+            Some(CborValue::Bytes(bytes)) if self.binary_fields => Ok(bytes),
+            Some(CborValue::Text(t)) if !self.binary_fields => Ok(hex::decode(t)?),
+            _ => Err(GxtError::Invalid),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Checks this implementation against the known-answer test vectors in `test-vectors/`.
+
+use gxt::JsonValue;
+use serde::Deserialize;
+use vectors::{Operation, Vector, error_name, run};
+
+mod vectors;
+
+#[derive(Deserialize)]
+struct VectorFile {
+    vectors: Vec<Vector>,
+}
+
+const FILES: [&str; 6] = [
+    include_str!("../../test-vectors/keys.json"),
+    include_str!("../../test-vectors/id-cards.json"),
+    include_str!("../../test-vectors/signing.json"),
+    include_str!("../../test-vectors/encryption.json"),
+    include_str!("../../test-vectors/legacy.json"),
+    include_str!("../../test-vectors/errors.json"),
+];
+
+fn parse(file: &str) -> Vec<Vector> {
+    let file: VectorFile = serde_json::from_str(file).expect("valid test vectors");
+    assert!(!file.vectors.is_empty());
+    file.vectors
+}
+
+fn check(file: &str) {
+    for vector in &parse(file) {
+        match (run(vector), &vector.expected, &vector.error) {
+            (Ok(result), Some(expected), None) => {
+                assert_eq!(&result, expected, "{}", vector.name);
+            }
+            (Err(e), None, Some(expected)) => {
+                assert_eq!(&error_name(&e), expected, "{}: {e}", vector.name);
+            }
+            (Ok(_), _, Some(expected)) => panic!("{}: expected {expected}", vector.name),
+            (Err(e), _, _) => panic!("{}: {e:?}", vector.name),
+            (Ok(_), _, None) => panic!("{}: no expected result", vector.name),
+        }
+    }
+}
+
+#[test]
+fn keys() {
+    check(FILES[0]);
+}
+
+#[test]
+fn id_cards() {
+    check(FILES[1]);
+}
+
+#[test]
+fn signing() {
+    check(FILES[2]);
+}
+
+#[test]
+fn encryption() {
+    check(FILES[3]);
+}
+
+#[test]
+fn legacy() {
+    check(FILES[4]);
+}
+
+#[test]
+fn errors() {
+    check(FILES[5]);
+}
+
+/// Verifies the token of every `verify` vector together with the tokens of all valid ones,
+/// at several positions, so every bad token is verified among good ones only.
+#[test]
+fn verify_many() {
+    let vectors = FILES
+        .iter()
+        .flat_map(|file| parse(file))
+        .filter(|vector| matches!(vector.operation, Operation::Verify))
+        .collect::<Vec<_>>();
+    let valid = vectors
+        .iter()
+        .filter(|vector| vector.expected.is_some())
+        .map(|vector| vector.token.as_deref().unwrap_or_default())
+        .collect::<Vec<_>>();
+    for vector in &vectors {
+        for position in 0..8 {
+            let mut tokens = valid.repeat(2);
+            tokens.insert(position, vector.token.as_deref().unwrap_or_default());
+            let results = gxt::verify_many::<JsonValue, _>(&tokens);
+            assert_eq!(results.len(), tokens.len());
+            assert!(
+                results
+                    .iter()
+                    .enumerate()
+                    .all(|(i, result)| i == position || result.is_ok())
+            );
+            match (&results[position], &vector.expected, &vector.error) {
+                (Ok(result), Some(expected), None) => {
+                    assert_eq!(
+                        &serde_json::to_value(result).expect("json"),
+                        expected,
+                        "{}",
+                        vector.name
+                    );
+                }
+                (Err(e), None, Some(expected)) => {
+                    assert_eq!(&error_name(e), expected, "{}: {e}", vector.name);
+                }
+                (result, _, _) => panic!("{}: {:?}", vector.name, result.as_ref().map(|_| ())),
+            }
+        }
+    }
+}
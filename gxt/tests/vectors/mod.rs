@@ -0,0 +1,184 @@
+//! The format of the known-answer test vectors in `test-vectors/`,
+//! shared by `gxt/examples/test_vectors.rs` and the conformance tests.
+
+use gxt::{Encoding, GxtError, IdCard, Identity, JsonValue, Revocation, Revocations, Token, json};
+use serde::{Deserialize, Serialize};
+
+/// What a vector checks.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    /// Derives the public keys and the recovery phrase from a `gxk:` key
+    Derive,
+    /// Restores the `gxk:` key from a recovery phrase
+    Recover,
+    /// Unlocks a password protected `gxe:` key
+    Unlock,
+    /// Verifies a token and returns its envelope
+    Verify,
+    /// Verifies a token and rejects it if it is expired at `now`
+    VerifyAt,
+    /// Verifies a token and rejects it if its key was revoked by one of the `revocations`
+    VerifyWithRevocations,
+    /// Verifies and decrypts a message with `key`
+    Decrypt,
+    /// Verifies a detached signature over the hex encoded `data`
+    VerifyDetached,
+    /// Joins the `parts` of a multi-part token
+    Join,
+    /// Writes the token in the `encoding` with the given name, without decompressing it
+    Encode,
+    /// Re-signs the ID card in `token` of an older version with `key`
+    Upgrade,
+    /// Signs the `payload` with `key` as public message, addressed to the `recipients` if there are any
+    Sign,
+    /// Encrypts the `payload` with `key` for all `recipients` and returns the kind, the sender
+    /// and the encryption keys of the recipients of the verified message
+    Encrypt,
+}
+
+/// One operation, its inputs and either the `expected` result or the name of the `error` variant.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Vector {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    pub operation: Operation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recovery_phrase: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub now: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parts: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revocations: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Runs the operation of the vector.
+pub fn run(vector: &Vector) -> Result<JsonValue, GxtError> {
+    let token = vector.token.as_deref().unwrap_or_default();
+    let key = vector.key.as_deref().unwrap_or_default();
+    Ok(match vector.operation {
+        Operation::Derive => {
+            let identity = key.parse::<Identity>()?;
+            json!({
+                "verification_key": identity.verification_key(),
+                "encryption_key": identity.encryption_key(),
+                "recovery_phrase": identity.to_recovery_phrase(),
+            })
+        }
+        Operation::Recover => json!(gxt::key_from_recovery_phrase(
+            vector.recovery_phrase.as_deref().unwrap_or_default()
+        )?),
+        Operation::Unlock => json!(gxt::unlock_key(
+            token,
+            vector.password.as_deref().unwrap_or_default()
+        )?),
+        Operation::Verify => json!(gxt::verify_message::<JsonValue>(token)?),
+        Operation::VerifyAt => json!(gxt::verify_message_at::<JsonValue>(
+            token,
+            vector.now.unwrap_or_default()
+        )?),
+        Operation::VerifyWithRevocations => {
+            let revocations = vector
+                .revocations
+                .iter()
+                .flatten()
+                .map(|revocation| revocation.parse::<Revocation>())
+                .collect::<Result<Revocations, _>>()?;
+            json!(gxt::verify_message_with_revocations::<JsonValue>(
+                token,
+                &revocations
+            )?)
+        }
+        Operation::Decrypt => json!(gxt::decrypt_message::<JsonValue>(token, key)?),
+        Operation::VerifyDetached => {
+            let data = hex::decode(vector.data.as_deref().unwrap_or_default())?;
+            json!(gxt::verify_detached::<JsonValue>(token, &data)?)
+        }
+        Operation::Join => json!(gxt::join_parts(
+            vector.parts.as_deref().unwrap_or_default()
+        )?),
+        Operation::Encode => {
+            let encoding = vector
+                .encoding
+                .as_deref()
+                .unwrap_or_default()
+                .parse::<Encoding>()?;
+            json!(token.parse::<Token>()?.to_encoding(encoding)?.as_str())
+        }
+        Operation::Upgrade => json!(
+            key.parse::<Identity>()?
+                .upgrade_id_card(&token.parse()?)?
+                .token()
+                .verify::<JsonValue>()?
+        ),
+        Operation::Sign => {
+            let recipients = recipients(vector)?;
+            let token = key
+                .parse::<Identity>()?
+                .message()
+                .payload(vector.payload.as_ref().unwrap_or(&JsonValue::Null))
+                .to_all(&recipients)
+                .sign()?;
+            json!(token.verify::<JsonValue>()?)
+        }
+        Operation::Encrypt => {
+            let recipients = recipients(vector)?;
+            let envelope = key
+                .parse::<Identity>()?
+                .message()
+                .payload(vector.payload.as_ref().unwrap_or(&JsonValue::Null))
+                .to_all(&recipients)
+                .encrypt()?
+                .verify::<JsonValue>()?;
+            let to = match &envelope.payload["to"] {
+                JsonValue::Array(to) => to
+                    .iter()
+                    .map(|recipient| recipient["key"].clone())
+                    .collect(),
+                to => vec![to.clone()],
+            };
+            json!({
+                "kind": envelope.kind,
+                "verification_key": envelope.verification_key,
+                "to": to,
+            })
+        }
+    })
+}
+
+fn recipients(vector: &Vector) -> Result<Vec<IdCard>, GxtError> {
+    vector
+        .recipients
+        .iter()
+        .flatten()
+        .map(|id_card| id_card.parse())
+        .collect()
+}
+
+/// The name of the variant, e.g. `BadSig` or `TokenTooLong`.
+pub fn error_name(error: &GxtError) -> String {
+    format!("{error:?}")
+        .chars()
+        .take_while(char::is_ascii_alphanumeric)
+        .collect()
+}
@@ -4,6 +4,7 @@ import init, {
   encrypt_message,
   decrypt_message,
   verify_message,
+  extract_tokens,
 } from "./pkg/gxt_wasm.js";
 
 const kinds = ["k", "i", "m"];
@@ -111,7 +112,7 @@ function bindIdPanel() {
     const f = e.target.files?.[0];
     if (!f) return;
     const text = await readFileAsText(f);
-    const tokens = extractTokens(text);
+    const tokens = extract_tokens(String(text));
     // Dateiname ohne Endung als Label verwenden
     const base = (f.name || "").replace(/\.gx(k|m|i)$/i, "");
     let added = 0;
@@ -211,17 +212,6 @@ function bindIdPanel() {
   });
 }
 
-function extractTokens(text) {
-  const tokens = [];
-  for (const part of String(text).split(/\s+/)) {
-    const good_prefix = kinds.some((k) => {
-      return part.startsWith(`gx${k}:`);
-    });
-    if (good_prefix) tokens.push(part);
-  }
-  return tokens;
-}
-
 async function addIdCard(value, label) {
   const env = verify_message(value);
   if (env.kind !== "id" && env.kind !== "Id") throw new Error("Invalid Payload Kind");
@@ -352,7 +342,7 @@ async function exportIdCard(card) {
 $("#btnImportIdText")?.addEventListener("click", async () => {
   const text = ($("#idImportInput")?.value || "").trim();
   if (!text) return;
-  const tokens = extractTokens(text);
+  const tokens = extract_tokens(String(text));
   let added = 0,
     failed = 0;
   for (const tok of tokens) {